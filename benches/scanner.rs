@@ -0,0 +1,60 @@
+//! Scanner throughput, run with `cargo bench`
+
+use rlox::{Scanner, StdErrErrorHandler};
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+const ITERATIONS: u32 = 20;
+
+/// Mostly keywords, identifiers, operators, numbers and comments
+fn ascii_source(lines: usize) -> String {
+    let mut source = String::new();
+    for i in 0..lines {
+        source.push_str(&format!(
+            "fun f{0}(a, b) {{ if (a >= b) return a * {0}.5 - 0x1F; else return nil; }} // comment {0}\n",
+            i
+        ));
+        source.push_str(&format!("var x{0} = 1_000 + {0}e3; /* block */ while (x{0} < 10) x{0} = x{0} + 1;\n", i));
+    }
+    source
+}
+
+/// Mostly string literals, some with escapes and non-ASCII text
+fn string_source(lines: usize) -> String {
+    let mut source = String::new();
+    for i in 0..lines {
+        source.push_str(&format!(
+            "print \"plain string number {0}\" + \"escaped\\t\\\"{0}\\\"\\n\" + \"ünïcödé \\u{{1F600}} {0}\";\n",
+            i
+        ));
+    }
+    source
+}
+
+fn bench(name: &str, source: &str) {
+    let error_handler = StdErrErrorHandler::new();
+    let mut best = Duration::MAX;
+    let mut tokens = 0;
+    for _ in 0..ITERATIONS {
+        let start = Instant::now();
+        let mut scanner = Scanner::new(black_box(source), &error_handler);
+        tokens = black_box(scanner.scan_tokens()).len();
+        best = best.min(start.elapsed());
+    }
+
+    let seconds = best.as_secs_f64();
+    println!(
+        "{:<8} {:>6.2} MB {:>9} tokens {:>8.2} ms {:>7.2} Mtokens/s {:>7.1} MB/s",
+        name,
+        source.len() as f64 / 1e6,
+        tokens,
+        seconds * 1e3,
+        tokens as f64 / seconds / 1e6,
+        source.len() as f64 / seconds / 1e6
+    );
+}
+
+fn main() {
+    bench("ascii", &ascii_source(50_000));
+    bench("strings", &string_source(50_000));
+}
@@ -0,0 +1,3 @@
+pub use self::rlox::*;
+
+mod rlox;
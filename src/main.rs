@@ -1,48 +1,333 @@
+use std::cell::RefCell;
 use std::env;
 use std::fs;
 use std::io;
-use std::io::Write;
+use std::io::{IsTerminal, Read, Write};
 use std::process::exit;
+use std::rc::Rc;
+use std::thread;
 
-use rlox::Scanner;
-use crate::rlox::StdErrErrorHandler;
+use rlox::{
+    print_stmt, tokens_to_json, ErrorCode, Interpreter, Parser, Resolver, ScanError, Scanner, StdErrErrorHandler,
+    StreamScanner, StringInterner, Token, Warnings, WARNINGS,
+};
 
-mod rlox;
+const INTERPRETER_STACK_SIZE: usize = 64 * 1024 * 1024;
+// Source name of the lines typed at the prompt
+const REPL_NAME: &str = "<repl>";
 
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Mode {
+    Run,
+    // Only scan and print the tokens
+    Tokens,
+    // Only scan and print the tokens as a JSON array
+    JsonTokens,
+    // Only parse and print the syntax tree
+    Ast,
+}
+
+/// Settings from the command line
+#[derive(Debug, Clone)]
+struct Options {
+    mode: Mode,
+    // Diagnostics use ANSI colors
+    color: bool,
+    // Diagnostics are printed as JSON
+    json_diagnostics: bool,
+    warnings: Warnings,
+    max_errors: Option<usize>,
+}
 
 fn main() {
-    let args: Vec<String> = env::args().collect();
-    if args.len() > 2 {
-        println!("Usage: rlox [script]");
-        exit(64);
-    } else if args.len() == 2 {
-        run_file(&args[1]);
-    } else {
-        run_prompt();
+    let mut options = Options {
+        mode: Mode::Run,
+        color: io::stderr().is_terminal(),
+        json_diagnostics: false,
+        warnings: Warnings::default(),
+        max_errors: None,
+    };
+    let mut script = None;
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "-W" {
+            match args.next().as_deref() {
+                Some("all") => options.warnings.enable_all(),
+                Some("error") => options.warnings.set_as_errors(true),
+                Some(name) => options.warnings.enable(warning_code(name)),
+                None => usage(),
+            }
+        } else if arg == "-A" {
+            match args.next() {
+                Some(name) => options.warnings.allow(warning_code(&name)),
+                None => usage(),
+            }
+        } else if arg == "--tokens" {
+            // `--dump-tokens=json` already picks the tokens, in another format
+            if options.mode != Mode::JsonTokens {
+                options.mode = Mode::Tokens;
+            }
+        } else if arg == "--print-ast" {
+            options.mode = Mode::Ast;
+        } else if arg == "--dump-tokens=json" || arg == "--emit=json" {
+            options.mode = Mode::JsonTokens;
+        } else if let Some(when) = arg.strip_prefix("--color=") {
+            options.color = match when {
+                "always" => true,
+                "never" => false,
+                "auto" => io::stderr().is_terminal(),
+                _ => usage(),
+            };
+        } else if let Some(max_errors) = arg.strip_prefix("--max-errors=") {
+            options.max_errors = Some(max_errors.parse().unwrap_or_else(|_| usage()));
+        } else if arg == "--diagnostics=json" {
+            options.json_diagnostics = true;
+        } else if (arg.starts_with('-') && arg != "-") || script.is_some() {
+            usage();
+        } else {
+            script = Some(arg);
+        }
     }
+
+    // Lox calls recurse natively, the main thread stack is too small for `MAX_CALL_DEPTH` in debug builds
+    let interpreter = thread::Builder::new()
+        .stack_size(INTERPRETER_STACK_SIZE)
+        .spawn(move || match script.as_deref() {
+            // Tokens are printed as the lines come in
+            Some("-") if options.mode == Mode::Tokens => stream_tokens(&options),
+            Some("-") => run_program("<stdin>", &read_stdin(), &options),
+            Some(file_name) => run_program(file_name, &read_file(file_name), &options),
+            None => run_prompt(&options),
+        })
+        .expect("Could not start the interpreter thread");
+    if interpreter.join().is_err() {
+        exit(70);
+    }
+}
+
+fn usage() -> ! {
+    println!(
+        "Usage: rlox [--tokens] [--dump-tokens=json] [--print-ast] [--color=always|never|auto] [--diagnostics=json]\n            \
+         [--max-errors=<n>] [-W all|error|<warning>] [-A <warning>] [script | -]"
+    );
+    exit(64);
 }
 
-fn run_file(file_name: &str) {
-    println!("Reading {}", file_name);
-    let data = fs::read_to_string(file_name).unwrap();
-    run(&data);
+/// Warning named on the command line, by name or code
+fn warning_code(name: &str) -> ErrorCode {
+    match ErrorCode::warning(name) {
+        Some(code) => code,
+        None => {
+            let names: Vec<&str> = WARNINGS.iter().filter_map(|code| code.warning_name()).collect();
+            eprintln!("Unknown warning '{}', expected one of: {}", name, names.join(", "));
+            exit(64);
+        }
+    }
+}
+
+fn read_file(file_name: &str) -> String {
+    match fs::read_to_string(file_name) {
+        Ok(data) => data,
+        Err(err) => {
+            eprintln!("Could not read file '{}': {}", file_name, err);
+            exit(66);
+        }
+    }
+}
+
+fn read_stdin() -> String {
+    let mut data = String::new();
+    if let Err(err) = io::stdin().read_to_string(&mut data) {
+        eprintln!("Could not read stdin: {}", err);
+        exit(66);
+    }
+    data
 }
 
-fn run_prompt() {
+fn stream_tokens(options: &Options) {
+    let error_handler = StdErrErrorHandler::with_name("<stdin>")
+        .colored(options.color)
+        .json(options.json_diagnostics)
+        .max_errors(options.max_errors);
+    let mut scanner = StreamScanner::new(io::stdin().lock(), &error_handler);
+    for token in &mut scanner {
+        match token {
+            Ok(token) => println!("{}", token),
+            Err(err) => {
+                eprintln!("Could not read stdin: {}", err);
+                exit(66);
+            }
+        }
+    }
+    if scanner.had_errors() {
+        exit(RunError::Compile.exit_code());
+    }
+}
+
+fn run_program(name: &str, program: &str, options: &Options) {
+    if let Err(err) = run_source(&mut Interpreter::new(), name, program, false, options) {
+        exit(err.exit_code());
+    }
+}
+
+fn run_prompt(options: &Options) {
     let input = io::stdin();
+    let mut interpreter = Interpreter::new();
     let mut line = String::new();
+    // Lines of an input that isn't complete yet
+    let mut buffer = String::new();
     loop {
-        print!("> ");
+        print!("{}", if buffer.is_empty() { "> " } else { "... " });
         io::stdout().flush().unwrap();
-        input.read_line(&mut line).unwrap();
-        run(&line);
+        line.clear();
+        match input.read_line(&mut line) {
+            Ok(0) => break,
+            Ok(_) => {
+                // A blank line abandons an unfinished input, unless it is part of a comment or text block
+                if !buffer.is_empty() && line.trim().is_empty() && !ends_in_multi_line_token(&buffer) {
+                    buffer.clear();
+                    continue;
+                }
+                buffer.push_str(&line);
+                if has_open_brackets(&buffer) {
+                    continue;
+                }
+
+                // Errors are already reported, the session goes on
+                let _ = run_source(&mut interpreter, REPL_NAME, &buffer, true, options);
+                buffer.clear();
+            }
+            Err(err) => {
+                eprintln!("Could not read input: {}", err);
+                break;
+            }
+        }
+    }
+}
+
+/// Whether some `(` or `{` is still unclosed, brackets in strings and comments don't count.
+/// Input that doesn't even scan is considered complete, so the errors get reported, unless
+/// all it lacks is the end of a comment, text block or interpolation
+fn has_open_brackets(program: &str) -> bool {
+    let tokens = match rlox::scan(program) {
+        Ok(tokens) => tokens,
+        Err(errors) => return errors.iter().all(|error| is_open_at_end(error, program)),
+    };
+    let depth = tokens.iter().fold(0i32, |depth, token| match token.token() {
+        // An interpolation is closed by a `}`
+        Token::LeftParen | Token::LeftBrace | Token::Interpolation(_) => depth + 1,
+        Token::RightParen | Token::RightBrace => depth - 1,
+        _ => depth,
+    });
+    depth > 0
+}
+
+/// Scan error that more lines may fix, such as a `/*` without its `*/`
+fn is_open_at_end(error: &ScanError, program: &str) -> bool {
+    is_multi_line_token_at_end(error, program) || error.code == ErrorCode::UnterminatedInterpolation
+}
+
+/// Whether `program` ends inside a comment, text block or raw string, which may run over several lines
+fn ends_in_multi_line_token(program: &str) -> bool {
+    match rlox::scan(program) {
+        Ok(_) => false,
+        Err(errors) => errors.iter().any(|error| is_multi_line_token_at_end(error, program)),
+    }
+}
+
+fn is_multi_line_token_at_end(error: &ScanError, program: &str) -> bool {
+    // A plain string ends at the line break, which doesn't make it go on
+    matches!(error.code, ErrorCode::UnterminatedComment | ErrorCode::UnterminatedString) && error.span.end == program.len()
+}
+
+/// Reason the program didn't run to completion, the errors themselves are already reported
+#[derive(Debug)]
+enum RunError {
+    Compile,
+    Runtime,
+}
+
+impl RunError {
+    fn exit_code(&self) -> i32 {
+        match self {
+            RunError::Compile => 65,
+            RunError::Runtime => 70,
+        }
     }
 }
 
-fn run(program: &str) {
-    let mut scanner = Scanner::new(program, &StdErrErrorHandler {});
+/// Handles `program` as the mode says, `name` is where it comes from as shown in diagnostics
+fn run_source(interpreter: &mut Interpreter, name: &str, program: &str, repl: bool, options: &Options) -> Result<(), RunError> {
+    let error_handler = StdErrErrorHandler::with_file(name, program)
+        .colored(options.color)
+        .json(options.json_diagnostics)
+        .max_errors(options.max_errors);
+    match options.mode {
+        Mode::Run => run(interpreter, program, repl, &error_handler, &options.warnings),
+        Mode::Tokens => print_tokens(program, false, &error_handler),
+        Mode::JsonTokens => print_tokens(program, true, &error_handler),
+        Mode::Ast => print_ast(program, &error_handler),
+    }
+}
+
+fn print_tokens(program: &str, json: bool, error_handler: &StdErrErrorHandler) -> Result<(), RunError> {
+    let mut scanner = Scanner::new(program, error_handler);
     let tokens = scanner.scan_tokens();
-    for token in tokens {
-        println!("{:?}", token);
+    if json {
+        println!("{}", tokens_to_json(tokens, program));
+    } else {
+        for token in tokens {
+            println!("{}", token);
+        }
+    }
+    if scanner.had_errors() {
+        Err(RunError::Compile)
+    } else {
+        Ok(())
+    }
+}
+
+fn print_ast(program: &str, error_handler: &StdErrErrorHandler) -> Result<(), RunError> {
+    let mut scanner = Scanner::new(program, error_handler);
+    let tokens = scanner.scan_tokens();
+    let mut parser = Parser::new(tokens, error_handler);
+    for statement in parser.parse() {
+        println!("{}", print_stmt(&statement));
+    }
+    if parser.had_errors() || scanner.had_errors() {
+        Err(RunError::Compile)
+    } else {
+        Ok(())
+    }
+}
+
+/// In `repl` mode a trailing expression is evaluated and its value printed
+fn run(
+    interpreter: &mut Interpreter,
+    program: &str,
+    repl: bool,
+    error_handler: &StdErrErrorHandler,
+    warnings: &Warnings,
+) -> Result<(), RunError> {
+    // Shared so that the resolver compares the symbols of names instead of the names
+    let interner = Rc::new(RefCell::new(StringInterner::new()));
+    let mut scanner = Scanner::with_interner(program, error_handler, interner.clone());
+    let tokens = scanner.scan_tokens();
+    let mut parser = Parser::new(tokens, error_handler);
+    let statements = if repl { parser.parse_repl() } else { parser.parse() };
+    if parser.had_errors() || scanner.had_errors() {
+        return Err(RunError::Compile);
+    }
+
+    let mut resolver = Resolver::with_warnings(interpreter, error_handler, warnings.clone()).sharing_interner(interner);
+    resolver.resolve(&statements);
+    if resolver.had_errors() {
+        return Err(RunError::Compile);
+    }
+
+    if interpreter.interpret(&statements, error_handler) {
+        Ok(())
+    } else {
+        Err(RunError::Runtime)
     }
 }
@@ -1,5 +1,31 @@
-pub use self::scanner::Scanner;
-pub use self::error_handler::{ErrorHandler, StdErrErrorHandler};
+pub use self::ast::{BinaryOperator, Expr, ExprId, FunctionDecl, Identifier, LogicalOperator, Stmt, UnaryOperator};
+pub use self::ast_printer::{print_expr, print_stmt};
+pub use self::class::{LoxClass, LoxInstance};
+pub use self::environment::Environment;
+pub use self::function::{LoxCallable, LoxFunction, NativeFn, NativeFunction};
+pub use self::interner::{StringInterner, Symbol};
+pub use self::json::{diagnostic_to_json, tokens_to_json};
+pub use self::interpreter::{Interpreter, RuntimeError, StackFrame};
+pub use self::parser::Parser;
+pub use self::resolver::Resolver;
+pub use self::scanner::{scan, ScanError, ScanStats, Scanner, Span, StreamScanner, Token, TokenInfo, Tokens};
+pub use self::error_code::{ErrorCode, Warnings, WARNINGS};
+pub use self::error_handler::{CollectingErrorHandler, Diagnostic, ErrorHandler, Severity, StdErrErrorHandler};
+pub use self::value::Value;
 
+mod ast;
+mod ast_printer;
 mod scanner;
+mod interner;
+mod parser;
+mod resolver;
+mod interpreter;
+mod environment;
+mod function;
+mod class;
+mod natives;
+mod error_code;
 mod error_handler;
+mod json;
+mod unicode_xid;
+mod value;
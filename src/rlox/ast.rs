@@ -0,0 +1,195 @@
+use super::interner::Symbol;
+use super::value::Value;
+use std::fmt;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum UnaryOperator {
+    Negate,
+    Not,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BinaryOperator {
+    Equal,
+    NotEqual,
+    Greater,
+    GreaterEqual,
+    Less,
+    LessEqual,
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Remainder,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LogicalOperator {
+    And,
+    Or,
+}
+
+impl fmt::Display for UnaryOperator {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            UnaryOperator::Negate => "-",
+            UnaryOperator::Not => "!",
+        })
+    }
+}
+
+impl fmt::Display for BinaryOperator {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            BinaryOperator::Equal => "==",
+            BinaryOperator::NotEqual => "!=",
+            BinaryOperator::Greater => ">",
+            BinaryOperator::GreaterEqual => ">=",
+            BinaryOperator::Less => "<",
+            BinaryOperator::LessEqual => "<=",
+            BinaryOperator::Add => "+",
+            BinaryOperator::Subtract => "-",
+            BinaryOperator::Multiply => "*",
+            BinaryOperator::Divide => "/",
+            BinaryOperator::Remainder => "%",
+        })
+    }
+}
+
+impl fmt::Display for LogicalOperator {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            LogicalOperator::And => "and",
+            LogicalOperator::Or => "or",
+        })
+    }
+}
+
+/// Identity of an expression node, used to attach resolver results to variable references
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ExprId(usize);
+
+impl ExprId {
+    /// Ids are unique across parsers, so resolutions of different REPL lines never clash
+    pub fn unique() -> ExprId {
+        static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+        ExprId(NEXT_ID.fetch_add(1, Ordering::Relaxed))
+    }
+}
+
+/// A name in the source along with its position
+#[derive(Debug, Clone, PartialEq)]
+pub struct Identifier {
+    pub lexeme: String,
+    pub line: u32,
+    pub column: u32,
+    // Interned `lexeme`, set when the scanner interns identifiers
+    pub symbol: Option<Symbol>,
+}
+
+#[derive(Debug, Clone)]
+pub enum Expr {
+    Binary {
+        left: Box<Expr>,
+        operator: BinaryOperator,
+        right: Box<Expr>,
+        line: u32,
+    },
+    Logical {
+        left: Box<Expr>,
+        operator: LogicalOperator,
+        right: Box<Expr>,
+    },
+    Unary {
+        operator: UnaryOperator,
+        right: Box<Expr>,
+        line: u32,
+    },
+    Literal(Value),
+    Grouping(Box<Expr>),
+    Variable {
+        name: Identifier,
+        id: ExprId,
+    },
+    Assign {
+        name: Identifier,
+        value: Box<Expr>,
+        id: ExprId,
+    },
+    Call {
+        callee: Box<Expr>,
+        arguments: Vec<Expr>,
+        // Line of the closing parenthesis
+        line: u32,
+    },
+    Get {
+        object: Box<Expr>,
+        name: Identifier,
+    },
+    Set {
+        object: Box<Expr>,
+        name: Identifier,
+        value: Box<Expr>,
+    },
+    This {
+        keyword: Identifier,
+        id: ExprId,
+    },
+    Super {
+        keyword: Identifier,
+        method: Identifier,
+        id: ExprId,
+    },
+    // Anonymous `fun (params) { body }`, named `anonymous`
+    Lambda(Rc<FunctionDecl>),
+    // `"a ${b} c"`, the parts are converted to strings and concatenated
+    Interpolation(Vec<Expr>),
+}
+
+#[derive(Debug, Clone)]
+pub enum Stmt {
+    Expression(Expr),
+    Print(Expr),
+    Var {
+        name: Identifier,
+        initializer: Option<Expr>,
+        // From `///` comments before the declaration
+        doc: Option<String>,
+    },
+    Block(Vec<Stmt>),
+    If {
+        condition: Expr,
+        then_branch: Box<Stmt>,
+        else_branch: Option<Box<Stmt>>,
+    },
+    While {
+        condition: Expr,
+        body: Box<Stmt>,
+        // Increment of a desugared `for`, runs after the body even on `continue`
+        increment: Option<Expr>,
+    },
+    Function(Rc<FunctionDecl>),
+    Return {
+        keyword: Identifier,
+        value: Option<Expr>,
+    },
+    Break,
+    Continue,
+    Class {
+        name: Identifier,
+        // Always an `Expr::Variable`
+        superclass: Option<Expr>,
+        methods: Vec<Rc<FunctionDecl>>,
+        doc: Option<String>,
+    },
+}
+
+#[derive(Debug)]
+pub struct FunctionDecl {
+    pub name: Identifier,
+    pub params: Vec<Identifier>,
+    pub body: Vec<Stmt>,
+    pub doc: Option<String>,
+}
@@ -0,0 +1,86 @@
+use super::ast::{Expr, FunctionDecl, Stmt};
+
+/// Renders an expression as a Lisp-like s-expression, e.g. `(* (- 123) (group 45.67))`
+pub fn print_expr(expr: &Expr) -> String {
+    match expr {
+        Expr::Binary { left, operator, right, .. } => parenthesize(&operator.to_string(), &[left, right]),
+        Expr::Logical { left, operator, right } => parenthesize(&operator.to_string(), &[left, right]),
+        Expr::Unary { operator, right, .. } => parenthesize(&operator.to_string(), &[right]),
+        Expr::Literal(value) => value.to_string(),
+        Expr::Grouping(expr) => parenthesize("group", &[expr]),
+        Expr::Variable { name, .. } => name.lexeme.clone(),
+        Expr::Assign { name, value, .. } => parenthesize(&format!("= {}", name.lexeme), &[value]),
+        Expr::Call { callee, arguments, .. } => {
+            let mut exprs = vec![callee.as_ref()];
+            exprs.extend(arguments);
+            parenthesize("call", &exprs)
+        }
+        Expr::Get { object, name } => parenthesize(&format!(". {}", name.lexeme), &[object]),
+        Expr::Set { object, name, value } => parenthesize(&format!("= .{}", name.lexeme), &[object, value]),
+        Expr::This { .. } => "this".to_string(),
+        Expr::Super { method, .. } => format!("super.{}", method.lexeme),
+        Expr::Lambda(declaration) => print_function("fun", declaration),
+        Expr::Interpolation(parts) => parenthesize("str", &parts.iter().collect::<Vec<_>>()),
+    }
+}
+
+/// Renders a statement in the same style, e.g. `(while (< i 3) (block (print i)))`
+pub fn print_stmt(stmt: &Stmt) -> String {
+    match stmt {
+        Stmt::Expression(expr) => parenthesize(";", &[expr]),
+        Stmt::Print(expr) => parenthesize("print", &[expr]),
+        Stmt::Var { name, initializer: Some(initializer), .. } => {
+            parenthesize(&format!("var {}", name.lexeme), &[initializer])
+        }
+        Stmt::Var { name, initializer: None, .. } => format!("(var {})", name.lexeme),
+        Stmt::Block(statements) => group("block", statements.iter().map(print_stmt)),
+        Stmt::If { condition, then_branch, else_branch } => {
+            let mut parts = vec![print_expr(condition), print_stmt(then_branch)];
+            parts.extend(else_branch.iter().map(|else_branch| print_stmt(else_branch)));
+            group("if", parts.into_iter())
+        }
+        Stmt::While { condition, body, increment } => {
+            let mut parts = vec![print_expr(condition), print_stmt(body)];
+            parts.extend(increment.iter().map(print_expr));
+            group("while", parts.into_iter())
+        }
+        Stmt::Function(declaration) => print_function(&format!("fun {}", declaration.name.lexeme), declaration),
+        Stmt::Return { value: Some(value), .. } => parenthesize("return", &[value]),
+        Stmt::Return { value: None, .. } => "(return)".to_string(),
+        Stmt::Break => "(break)".to_string(),
+        Stmt::Continue => "(continue)".to_string(),
+        Stmt::Class { name, superclass, methods, .. } => {
+            let mut header = format!("class {}", name.lexeme);
+            if let Some(superclass) = superclass {
+                header.push_str(&format!(" < {}", print_expr(superclass)));
+            }
+            group(&header, methods.iter().map(|method| print_function(&method.name.lexeme, method)))
+        }
+    }
+}
+
+fn print_function(name: &str, declaration: &FunctionDecl) -> String {
+    let params: Vec<&str> = declaration.params.iter().map(|param| param.lexeme.as_str()).collect();
+    let header = format!("{} ({})", name, params.join(" "));
+    group(&header, declaration.body.iter().map(print_stmt))
+}
+
+fn group(name: &str, parts: impl Iterator<Item = String>) -> String {
+    let mut result = format!("({}", name);
+    for part in parts {
+        result.push(' ');
+        result.push_str(&part);
+    }
+    result.push(')');
+    result
+}
+
+fn parenthesize(name: &str, exprs: &[&Expr]) -> String {
+    let mut result = format!("({}", name);
+    for expr in exprs {
+        result.push(' ');
+        result.push_str(&print_expr(expr));
+    }
+    result.push(')');
+    result
+}
@@ -0,0 +1,94 @@
+use super::function::{LoxCallable, LoxFunction};
+use super::interpreter::{Interpreter, RuntimeError};
+use super::value::Value;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::rc::Rc;
+
+#[derive(Debug)]
+pub struct LoxClass {
+    name: String,
+    superclass: Option<Rc<LoxClass>>,
+    methods: HashMap<String, Rc<LoxFunction>>,
+    doc: Option<String>,
+}
+
+impl LoxClass {
+    pub fn new(
+        name: &str,
+        superclass: Option<Rc<LoxClass>>,
+        methods: HashMap<String, Rc<LoxFunction>>,
+        doc: Option<String>,
+    ) -> LoxClass {
+        LoxClass { name: name.to_string(), superclass, methods, doc }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn doc(&self) -> Option<&str> {
+        self.doc.as_deref()
+    }
+
+    /// Looks the method up on the class and then along the superclass chain
+    pub fn find_method(&self, name: &str) -> Option<Rc<LoxFunction>> {
+        match self.methods.get(name) {
+            Some(method) => Some(method.clone()),
+            None => self.superclass.as_ref().and_then(|superclass| superclass.find_method(name)),
+        }
+    }
+}
+
+/// Calling a class creates a new instance of it and runs `init` with the arguments
+impl LoxCallable for Rc<LoxClass> {
+    fn arity(&self) -> usize {
+        self.find_method("init").map_or(0, |initializer| initializer.arity())
+    }
+
+    fn call(&self, interpreter: &mut Interpreter, arguments: Vec<Value>, line: u32) -> Result<Value, RuntimeError> {
+        let instance = Value::Instance(Rc::new(RefCell::new(LoxInstance::new(self.clone()))));
+        if let Some(initializer) = self.find_method("init") {
+            initializer.bind(instance.clone()).call(interpreter, arguments, line)?;
+        }
+        Ok(instance)
+    }
+}
+
+pub struct LoxInstance {
+    class: Rc<LoxClass>,
+    fields: HashMap<String, Value>,
+}
+
+impl LoxInstance {
+    pub fn new(class: Rc<LoxClass>) -> LoxInstance {
+        LoxInstance { class, fields: HashMap::new() }
+    }
+
+    pub fn class(&self) -> &Rc<LoxClass> {
+        &self.class
+    }
+
+    /// Fields shadow methods of the same name, methods are returned bound to the instance
+    pub fn get(instance: &Rc<RefCell<LoxInstance>>, name: &str) -> Option<Value> {
+        let this = instance.borrow();
+        if let Some(value) = this.fields.get(name) {
+            return Some(value.clone());
+        }
+
+        let method = this.class.find_method(name)?;
+        Some(Value::Function(Rc::new(method.bind(Value::Instance(instance.clone())))))
+    }
+
+    pub fn set(&mut self, name: &str, value: Value) {
+        self.fields.insert(name.to_string(), value);
+    }
+}
+
+impl fmt::Debug for LoxInstance {
+    // Fields may refer back to the instance, so they are not printed
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "LoxInstance({})", self.class.name)
+    }
+}
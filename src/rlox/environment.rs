@@ -0,0 +1,85 @@
+use super::error_code::ErrorCode;
+use super::interpreter::RuntimeError;
+use super::value::Value;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+#[derive(Debug, Default)]
+pub struct Environment {
+    values: HashMap<String, Value>,
+    enclosing: Option<Rc<RefCell<Environment>>>,
+}
+
+impl Environment {
+    pub fn new() -> Environment {
+        Environment { values: HashMap::new(), enclosing: None }
+    }
+
+    /// A nested scope, lookups fall back to `enclosing` for names not defined here
+    pub fn with_enclosing(enclosing: Rc<RefCell<Environment>>) -> Environment {
+        Environment { values: HashMap::new(), enclosing: Some(enclosing) }
+    }
+
+    /// Defines a new variable or redefines an existing one
+    pub fn define(&mut self, name: &str, value: Value) {
+        self.values.insert(name.to_string(), value);
+    }
+
+    /// Assigns an existing variable, it is an error to assign an undeclared one
+    pub fn assign(&mut self, name: &str, value: Value, line: u32) -> Result<(), RuntimeError> {
+        if let Some(variable) = self.values.get_mut(name) {
+            *variable = value;
+            return Ok(());
+        }
+
+        match &self.enclosing {
+            Some(enclosing) => enclosing.borrow_mut().assign(name, value, line),
+            None => Err(undefined_variable(name, line)),
+        }
+    }
+
+    /// Looks up a variable exactly `distance` scopes up the chain, as computed by the resolver
+    pub fn get_at(&self, distance: usize, name: &str, line: u32) -> Result<Value, RuntimeError> {
+        if distance == 0 {
+            return self.values.get(name).cloned().ok_or_else(|| undefined_variable(name, line));
+        }
+
+        match &self.enclosing {
+            Some(enclosing) => enclosing.borrow().get_at(distance - 1, name, line),
+            None => Err(undefined_variable(name, line)),
+        }
+    }
+
+    pub fn assign_at(&mut self, distance: usize, name: &str, value: Value, line: u32) -> Result<(), RuntimeError> {
+        if distance == 0 {
+            return match self.values.get_mut(name) {
+                Some(variable) => {
+                    *variable = value;
+                    Ok(())
+                }
+                None => Err(undefined_variable(name, line)),
+            };
+        }
+
+        match &self.enclosing {
+            Some(enclosing) => enclosing.borrow_mut().assign_at(distance - 1, name, value, line),
+            None => Err(undefined_variable(name, line)),
+        }
+    }
+
+    pub fn get(&self, name: &str, line: u32) -> Result<Value, RuntimeError> {
+        if let Some(value) = self.values.get(name) {
+            return Ok(value.clone());
+        }
+
+        match &self.enclosing {
+            Some(enclosing) => enclosing.borrow().get(name, line),
+            None => Err(undefined_variable(name, line)),
+        }
+    }
+}
+
+fn undefined_variable(name: &str, line: u32) -> RuntimeError {
+    RuntimeError::new(line, ErrorCode::UndefinedVariable, &format!("Undefined variable '{}'.", name))
+}
@@ -0,0 +1,198 @@
+use super::error_handler::Severity;
+use std::collections::HashSet;
+use std::fmt;
+
+/// Stable identifier of a kind of diagnostic, for tools that match on it rather than on the message.
+/// The hundreds group the kinds: `E00xx` scanning, `E01xx` names, `E02xx` syntax, `E03xx` keywords used
+/// out of place, `E04xx` values of the wrong type and `E05xx` limits and host failures. `Wxxxx` are
+/// warnings. Codes are never reused for another kind
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ErrorCode {
+    // Scanning
+    UnterminatedString,
+    UnexpectedCharacter,
+    UnterminatedComment,
+    InvalidEscape,
+    InvalidNumber,
+    InvalidRawString,
+    UnterminatedInterpolation,
+
+    // Names
+    AlreadyDeclared,
+    UndefinedVariable,
+    UndefinedProperty,
+    ReadInOwnInitializer,
+
+    // Syntax
+    ExpectedExpression,
+    ExpectedToken,
+    TooManyArguments,
+    InvalidAssignmentTarget,
+
+    // Keywords out of place
+    TopLevelReturn,
+    OutsideLoop,
+    InitializerReturnsValue,
+    ThisOutsideClass,
+    InvalidSuper,
+
+    // Values of the wrong type
+    InvalidOperand,
+    NotCallable,
+    WrongArity,
+    NotAnInstance,
+    InvalidSuperclass,
+    InheritsFromItself,
+    InvalidArgument,
+
+    // Limits and the host
+    StackOverflow,
+    NativeFailure,
+
+    // Warnings
+    UnusedVariable,
+    Shadowing,
+}
+
+impl ErrorCode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ErrorCode::UnterminatedString => "E0001",
+            ErrorCode::UnexpectedCharacter => "E0002",
+            ErrorCode::UnterminatedComment => "E0003",
+            ErrorCode::InvalidEscape => "E0004",
+            ErrorCode::InvalidNumber => "E0005",
+            ErrorCode::InvalidRawString => "E0006",
+            ErrorCode::UnterminatedInterpolation => "E0007",
+
+            ErrorCode::AlreadyDeclared => "E0101",
+            ErrorCode::UndefinedVariable => "E0102",
+            ErrorCode::UndefinedProperty => "E0103",
+            ErrorCode::ReadInOwnInitializer => "E0104",
+
+            ErrorCode::ExpectedExpression => "E0201",
+            ErrorCode::ExpectedToken => "E0202",
+            ErrorCode::TooManyArguments => "E0203",
+            ErrorCode::InvalidAssignmentTarget => "E0204",
+
+            ErrorCode::TopLevelReturn => "E0301",
+            ErrorCode::OutsideLoop => "E0302",
+            ErrorCode::InitializerReturnsValue => "E0303",
+            ErrorCode::ThisOutsideClass => "E0304",
+            ErrorCode::InvalidSuper => "E0305",
+
+            ErrorCode::InvalidOperand => "E0401",
+            ErrorCode::NotCallable => "E0402",
+            ErrorCode::WrongArity => "E0403",
+            ErrorCode::NotAnInstance => "E0404",
+            ErrorCode::InvalidSuperclass => "E0405",
+            ErrorCode::InheritsFromItself => "E0406",
+            ErrorCode::InvalidArgument => "E0407",
+
+            ErrorCode::StackOverflow => "E0501",
+            ErrorCode::NativeFailure => "E0502",
+
+            ErrorCode::UnusedVariable => "W0001",
+            ErrorCode::Shadowing => "W0002",
+        }
+    }
+
+    /// Name of a warning for `-W` and `-A`, `None` for errors
+    pub fn warning_name(&self) -> Option<&'static str> {
+        match self {
+            ErrorCode::UnusedVariable => Some("unused-variable"),
+            ErrorCode::Shadowing => Some("shadowing"),
+            _ => None,
+        }
+    }
+
+    /// Warning by its name or code
+    pub fn warning(name: &str) -> Option<ErrorCode> {
+        WARNINGS.iter().copied().find(|code| code.warning_name() == Some(name) || code.as_str() == name)
+    }
+}
+
+pub const WARNINGS: [ErrorCode; 2] = [ErrorCode::UnusedVariable, ErrorCode::Shadowing];
+
+/// Which warnings are reported, and whether they count as errors
+#[derive(Debug, Clone, PartialEq)]
+pub struct Warnings {
+    enabled: HashSet<ErrorCode>,
+    as_errors: bool,
+}
+
+impl Warnings {
+    pub fn enable(&mut self, code: ErrorCode) {
+        self.enabled.insert(code);
+    }
+
+    pub fn enable_all(&mut self) {
+        self.enabled.extend(WARNINGS);
+    }
+
+    pub fn allow(&mut self, code: ErrorCode) {
+        self.enabled.remove(&code);
+    }
+
+    pub fn set_as_errors(&mut self, as_errors: bool) {
+        self.as_errors = as_errors;
+    }
+
+    /// How the warning is reported, `None` when it isn't
+    pub fn severity(&self, code: ErrorCode) -> Option<Severity> {
+        if !self.enabled.contains(&code) {
+            None
+        } else if self.as_errors {
+            Some(Severity::Error)
+        } else {
+            Some(Severity::Warning)
+        }
+    }
+}
+
+/// Shadowing is legitimate often enough to be only reported on request
+impl Default for Warnings {
+    fn default() -> Warnings {
+        Warnings { enabled: HashSet::from([ErrorCode::UnusedVariable]), as_errors: false }
+    }
+}
+
+impl fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn warning_severity() {
+        let mut warnings = Warnings::default();
+        assert_eq!(warnings.severity(ErrorCode::UnusedVariable), Some(Severity::Warning));
+        assert_eq!(warnings.severity(ErrorCode::Shadowing), None);
+
+        // -W all
+        warnings.enable_all();
+        assert_eq!(warnings.severity(ErrorCode::Shadowing), Some(Severity::Warning));
+
+        // -W error
+        warnings.set_as_errors(true);
+        assert_eq!(warnings.severity(ErrorCode::UnusedVariable), Some(Severity::Error));
+        assert_eq!(warnings.severity(ErrorCode::Shadowing), Some(Severity::Error));
+
+        // -A unused-variable
+        warnings.allow(ErrorCode::UnusedVariable);
+        assert_eq!(warnings.severity(ErrorCode::UnusedVariable), None);
+        assert_eq!(warnings.severity(ErrorCode::Shadowing), Some(Severity::Error));
+    }
+
+    #[test]
+    fn warnings_by_name_or_code() {
+        assert_eq!(ErrorCode::warning("shadowing"), Some(ErrorCode::Shadowing));
+        assert_eq!(ErrorCode::warning("W0001"), Some(ErrorCode::UnusedVariable));
+        assert_eq!(ErrorCode::warning("E0102"), None);
+        assert_eq!(ErrorCode::warning("unknown"), None);
+    }
+}
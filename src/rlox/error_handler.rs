@@ -1,18 +1,363 @@
+use super::error_code::ErrorCode;
+use super::interpreter::RuntimeError;
+use super::json::diagnostic_to_json;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex, MutexGuard};
 use std::fmt::Debug;
 
-pub trait ErrorHandler : Debug {
-    fn error(&self, line: u32, message: &str) {
-        self.report(line, "", message);
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Severity {
+    Error,
+    // Reported, but doesn't stop the program from running
+    Warning,
+}
+
+/// Methods take `&self` so one handler can serve the scanner, parser, resolver and interpreter at once.
+/// Handlers are `Send + Sync`, a host can share one between threads with an `Arc`, and keep state
+/// behind a `Mutex` or atomics like `CollectingErrorHandler`
+pub trait ErrorHandler : Debug + Send + Sync {
+    /// Every scan, parse and resolve diagnostic ends up here
+    fn emit(&self, diagnostic: &Diagnostic);
+
+    fn diagnostic(&self, severity: Severity, code: ErrorCode, line: u32, column: u32, message: &str) {
+        self.emit(&Diagnostic {
+            severity,
+            code,
+            line,
+            column,
+            length: 1,
+            position: String::new(),
+            message: message.to_string(),
+        });
+    }
+
+    fn error(&self, code: ErrorCode, line: u32, column: u32, message: &str) {
+        self.diagnostic(Severity::Error, code, line, column, message);
     }
 
-    fn report(&self, line: u32, position: &str, message: &str);
+    /// Same as `error`, followed by line `line` of `source` with a `^` under `column` on stderr.
+    /// Handlers that show the source themselves override it, so the line isn't shown twice
+    fn report_with_source(&self, source: &str, code: ErrorCode, line: u32, column: u32, message: &str) {
+        self.error(code, line, column, message);
+        if let Some((text, underline)) = underlined_line(source, line, column, 1) {
+            eprintln!("{}\n{}", text, underline);
+        }
+    }
+
+    /// Errors raised while evaluating the program, as opposed to scan and parse errors
+    fn runtime_error(&self, error: &RuntimeError) {
+        self.emit(&error.diagnostic());
+    }
+}
+
+/// Line `line` of `source` and `^` under the `length` bytes from the 1-based byte `column`,
+/// cut at the end of the line and at least one `^`
+fn underlined_line(source: &str, line: u32, column: u32, length: usize) -> Option<(&str, String)> {
+    let text = source.lines().nth(line.checked_sub(1)? as usize)?;
+    let offset = (column.saturating_sub(1) as usize).min(text.len());
+    // Tabs are kept so the carets line up with the text above
+    let mut underline: String = text.get(..offset)?.chars()
+        .map(|c| if c == '\t' { '\t' } else { ' ' })
+        .collect();
+    let end = (offset + length).min(text.len());
+    let carets = text.get(offset..end).map_or(0, |text| text.chars().count()).max(1);
+    underline.push_str(&"^".repeat(carets));
+    Some((text, underline))
 }
 
-#[derive(Debug)]
-pub struct StdErrErrorHandler {}
+// ANSI styles of the parts of a diagnostic
+const RED: &str = "1;31";
+const YELLOW: &str = "1;33";
+const BLUE: &str = "1;34";
+const CYAN: &str = "36";
+const BOLD: &str = "1";
+
+// Frames of a stack trace shown before the middle is cut
+const MAX_TRACE_LINES: usize = 20;
+
+/// Prints errors to stderr, rustc style with the offending source line when created `with_source`:
+///
+/// ```text
+/// error at ';': Expect expression.
+///  --> script.lox:1:9
+///   |
+/// 1 | print 1 +;
+///   |          ^
+/// ```
+#[derive(Debug, Default)]
+pub struct StdErrErrorHandler {
+    source: Option<String>,
+    // Shown in the location of a diagnostic
+    name: Option<String>,
+    // ANSI colors, for a terminal
+    color: bool,
+    // A JSON object per line instead of text, for tools
+    json: bool,
+    // Errors past this many aren't shown
+    max_errors: Option<usize>,
+    errors: AtomicUsize,
+}
+
+impl StdErrErrorHandler {
+    pub fn new() -> StdErrErrorHandler {
+        StdErrErrorHandler::default()
+    }
+
+    pub fn with_source(source: &str) -> StdErrErrorHandler {
+        StdErrErrorHandler { source: Some(source.to_string()), ..StdErrErrorHandler::new() }
+    }
+
+    /// Names the input in the diagnostics without keeping its source, such as `<stdin>` read as a stream
+    pub fn with_name(name: &str) -> StdErrErrorHandler {
+        StdErrErrorHandler { name: Some(name.to_string()), ..StdErrErrorHandler::new() }
+    }
+
+    /// Same as `with_source`, `name` is the file the source was read from
+    pub fn with_file(name: &str, source: &str) -> StdErrErrorHandler {
+        StdErrErrorHandler { source: Some(source.to_string()), name: Some(name.to_string()), ..StdErrErrorHandler::new() }
+    }
+
+    /// Highlights the parts of diagnostics with ANSI colors when `color` is set
+    pub fn colored(mut self, color: bool) -> StdErrErrorHandler {
+        self.color = color;
+        self
+    }
+
+    /// Prints each diagnostic as a line of JSON, see `diagnostic_to_json`
+    pub fn json(mut self, json: bool) -> StdErrErrorHandler {
+        self.json = json;
+        self
+    }
+
+    /// Shows only the first `max_errors` errors and a note that there are more, warnings are always shown
+    pub fn max_errors(mut self, max_errors: Option<usize>) -> StdErrErrorHandler {
+        self.max_errors = max_errors;
+        self
+    }
+
+    /// Lines like `at greet (script.lox:12)`, cut in the middle when recursion made them too many
+    fn render_trace(&self, error: &RuntimeError) -> String {
+        let trace = error.stack_trace();
+        let gutter = " ".repeat(error.line.to_string().len());
+        let mut text = String::new();
+        for (i, (function, line)) in trace.iter().enumerate() {
+            if trace.len() > MAX_TRACE_LINES && i >= MAX_TRACE_LINES / 2 && i < trace.len() - MAX_TRACE_LINES / 2 {
+                if i == MAX_TRACE_LINES / 2 {
+                    let hidden = trace.len() - MAX_TRACE_LINES / 2 * 2;
+                    text.push_str(&format!("\n{} {} ... {} more calls", gutter, self.paint(BLUE, "="), hidden));
+                }
+                continue;
+            }
+            let location = match &self.name {
+                Some(name) => format!("{}:{}", name, line),
+                None => format!("line {}", line),
+            };
+            text.push_str(&format!("\n{} {} at {} ({})", gutter, self.paint(BLUE, "="), function, self.paint(CYAN, &location)));
+        }
+        text
+    }
+
+    /// `text` in the ANSI `style` when colors are on
+    fn paint(&self, style: &str, text: &str) -> String {
+        if self.color {
+            format!("\x1b[{}m{}\x1b[0m", style, text)
+        } else {
+            text.to_string()
+        }
+    }
+
+    /// `source` is the code the diagnostic is about, usually the one the handler was created with
+    fn render(&self, diagnostic: &Diagnostic, source: Option<&str>) -> String {
+        if self.json {
+            return diagnostic_to_json(diagnostic, self.name.as_deref(), source);
+        }
+        let (label, style) = match diagnostic.severity {
+            Severity::Error => ("error", RED),
+            Severity::Warning => ("warning", YELLOW),
+        };
+        let heading = format!("{}[{}]{}", label, diagnostic.code, diagnostic.position);
+        let mut text = format!("{}: {}\n", self.paint(style, &heading), self.paint(BOLD, &diagnostic.message));
+
+        let name = self.name.as_deref().unwrap_or("line");
+        let separator = if self.name.is_some() { ":" } else { " " };
+        let mut location = format!("{}{}{}", name, separator, diagnostic.line);
+        // Runtime errors only know their line, nothing gets underlined
+        let snippet = if diagnostic.column == 0 {
+            source
+                .and_then(|source| source.lines().nth(diagnostic.line.checked_sub(1)? as usize))
+                .map(|line| (line, None))
+        } else {
+            location.push_str(&format!(":{}", diagnostic.column));
+            source
+                .and_then(|source| underlined_line(source, diagnostic.line, diagnostic.column, diagnostic.length))
+                .map(|(line, underline)| (line, Some(underline)))
+        };
+
+        // The gutter is as wide as the line number
+        let gutter = " ".repeat(diagnostic.line.to_string().len());
+        let bar = self.paint(BLUE, "|");
+        text.push_str(&format!("{}{} {}", gutter, self.paint(BLUE, "-->"), self.paint(CYAN, &location)));
+        if let Some((line, underline)) = snippet {
+            let number = self.paint(BLUE, &diagnostic.line.to_string());
+            text.push_str(&format!("\n{} {}\n{} {} {}", gutter, bar, number, bar, line));
+            if let Some(underline) = underline {
+                let (padding, carets) = underline.split_at(underline.find('^').unwrap_or(0));
+                text.push_str(&format!("\n{} {} {}{}", gutter, bar, padding, self.paint(style, carets)));
+            }
+        }
+        text
+    }
+
+    fn emit_with_source(&self, diagnostic: &Diagnostic, source: Option<&str>) {
+        if diagnostic.severity == Severity::Error {
+            let errors = self.errors.fetch_add(1, Ordering::Relaxed) + 1;
+            match self.max_errors {
+                // Not in JSON, which tools read line by line
+                Some(max_errors) if errors == max_errors + 1 && !self.json => {
+                    eprintln!("{}", self.paint(RED, &format!("Too many errors, only the first {} are shown.", max_errors)));
+                    return;
+                }
+                Some(max_errors) if errors > max_errors => return,
+                _ => {}
+            }
+        }
+        eprintln!("{}", self.render(diagnostic, source));
+    }
+}
 
 impl ErrorHandler for StdErrErrorHandler {
-    fn report(&self, line: u32, position: &str, message: &str) {
-        eprintln!("[line {}] Error{}: {}", line, position, message)
+    fn emit(&self, diagnostic: &Diagnostic) {
+        self.emit_with_source(diagnostic, self.source.as_deref());
+    }
+
+    /// The line of `source` is shown in place of one of the handler's own source
+    fn report_with_source(&self, source: &str, code: ErrorCode, line: u32, column: u32, message: &str) {
+        let diagnostic = Diagnostic {
+            severity: Severity::Error,
+            code,
+            line,
+            column,
+            length: 1,
+            position: String::new(),
+            message: message.to_string(),
+        };
+        self.emit_with_source(&diagnostic, Some(source));
+    }
+
+    fn runtime_error(&self, error: &RuntimeError) {
+        let mut text = self.render(&error.diagnostic(), self.source.as_deref());
+        if !self.json {
+            text.push_str(&self.render_trace(error));
+        }
+        eprintln!("{}", text);
+    }
+}
+
+/// Diagnostic recorded by `CollectingErrorHandler`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub code: ErrorCode,
+    pub line: u32,
+    pub column: u32,
+    // Bytes of source from `column` the diagnostic is about
+    pub length: usize,
+    // Such as ` at 'x'`, empty when the error isn't about a token
+    pub position: String,
+    pub message: String,
+}
+
+/// Keeps the diagnostics instead of printing them, for embedders that show them their own way
+#[derive(Debug, Default)]
+pub struct CollectingErrorHandler {
+    diagnostics: Mutex<Vec<Diagnostic>>,
+}
+
+impl CollectingErrorHandler {
+    pub fn new() -> CollectingErrorHandler {
+        CollectingErrorHandler { diagnostics: Mutex::new(vec![]) }
+    }
+
+    pub fn diagnostics(&self) -> Vec<Diagnostic> {
+        self.lock().clone()
+    }
+
+    /// Whether anything but warnings was reported
+    pub fn had_errors(&self) -> bool {
+        self.lock().iter().any(|diagnostic| diagnostic.severity == Severity::Error)
+    }
+
+    /// Returns the diagnostics recorded so far and forgets them
+    pub fn take(&self) -> Vec<Diagnostic> {
+        std::mem::take(&mut *self.lock())
     }
-}
\ No newline at end of file
+
+    // A panic while holding the lock can't leave the list half updated
+    fn lock(&self) -> MutexGuard<'_, Vec<Diagnostic>> {
+        self.diagnostics.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl ErrorHandler for CollectingErrorHandler {
+    fn emit(&self, diagnostic: &Diagnostic) {
+        self.lock().push(diagnostic.clone());
+    }
+
+    fn report_with_source(&self, _source: &str, code: ErrorCode, line: u32, column: u32, message: &str) {
+        self.error(code, line, column, message);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rlox::interpreter::StackFrame;
+
+    #[test]
+    fn underlined_source_line() {
+        let source = "var a = 1;\nprint a +;\n";
+        assert_eq!(underlined_line(source, 2, 10, 1), Some(("print a +;", "         ^".to_string())));
+        assert_eq!(underlined_line(source, 1, 5, 1), Some(("var a = 1;", "    ^".to_string())));
+        assert_eq!(underlined_line(source, 3, 1, 1), None);
+    }
+
+    #[test]
+    fn report_with_source_shows_the_line_once() {
+        let source = "print 1 +;";
+        let diagnostic = Diagnostic {
+            severity: Severity::Error,
+            code: ErrorCode::ExpectedExpression,
+            line: 1,
+            column: 10,
+            length: 1,
+            position: String::new(),
+            message: "Expect expression.".to_string(),
+        };
+        // A handler without a source of its own shows the one given
+        let text = StdErrErrorHandler::new().render(&diagnostic, Some(source));
+        assert_eq!(text.matches(source).count(), 1, "{}", text);
+        assert!(text.ends_with("|          ^"), "{}", text);
+
+        let handler = CollectingErrorHandler::new();
+        handler.report_with_source(source, ErrorCode::ExpectedExpression, 1, 10, "Expect expression.");
+        assert_eq!(handler.take(), vec![diagnostic]);
+    }
+
+    #[test]
+    fn long_stack_traces_are_cut() {
+        let mut error = RuntimeError::new(1, ErrorCode::StackOverflow, "Stack overflow.");
+        error.trace = (0..30).map(|i| StackFrame { function: format!("f{}", i), line: i + 2 }).collect();
+        let text = StdErrErrorHandler::with_name("a.lox").render_trace(&error);
+        let lines: Vec<&str> = text.lines().skip(1).collect();
+        assert_eq!(lines.len(), MAX_TRACE_LINES + 1, "{}", text);
+        assert_eq!(lines[0], "  = at f0 (a.lox:1)");
+        assert_eq!(lines[MAX_TRACE_LINES / 2 - 1], "  = at f9 (a.lox:10)");
+        assert_eq!(lines[MAX_TRACE_LINES / 2], "  = ... 11 more calls");
+        assert_eq!(lines[MAX_TRACE_LINES / 2 + 1], "  = at f21 (a.lox:22)");
+        assert_eq!(lines[MAX_TRACE_LINES], "  = at <script> (a.lox:31)");
+
+        // Short ones are shown whole
+        error.trace.truncate(3);
+        assert_eq!(StdErrErrorHandler::new().render_trace(&error).lines().count(), 1 + 4);
+    }
+}
@@ -0,0 +1,105 @@
+use super::ast::FunctionDecl;
+use super::environment::Environment;
+use super::interpreter::{Interpreter, RuntimeError, StackFrame};
+use super::value::Value;
+use std::cell::RefCell;
+use std::fmt;
+use std::rc::Rc;
+
+/// Anything a call expression can invoke
+pub trait LoxCallable {
+    fn arity(&self) -> usize;
+
+    /// Called with exactly `arity` arguments, `line` is where the call is
+    fn call(&self, interpreter: &mut Interpreter, arguments: Vec<Value>, line: u32) -> Result<Value, RuntimeError>;
+}
+
+/// A user-defined function together with the environment it was declared in
+pub struct LoxFunction {
+    declaration: Rc<FunctionDecl>,
+    closure: Rc<RefCell<Environment>>,
+    // An `init` method, which always returns `this`
+    is_initializer: bool,
+}
+
+impl LoxFunction {
+    pub fn new(declaration: Rc<FunctionDecl>, closure: Rc<RefCell<Environment>>) -> LoxFunction {
+        LoxFunction { declaration, closure, is_initializer: false }
+    }
+
+    pub fn initializer(declaration: Rc<FunctionDecl>, closure: Rc<RefCell<Environment>>) -> LoxFunction {
+        LoxFunction { declaration, closure, is_initializer: true }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.declaration.name.lexeme
+    }
+
+    pub fn arity(&self) -> usize {
+        self.declaration.params.len()
+    }
+
+    pub fn declaration(&self) -> &FunctionDecl {
+        &self.declaration
+    }
+
+    pub fn closure(&self) -> &Rc<RefCell<Environment>> {
+        &self.closure
+    }
+
+    pub fn is_initializer(&self) -> bool {
+        self.is_initializer
+    }
+
+    /// The same method with `this` bound to `instance`
+    pub fn bind(&self, instance: Value) -> LoxFunction {
+        let mut environment = Environment::with_enclosing(self.closure.clone());
+        environment.define("this", instance);
+        LoxFunction {
+            declaration: self.declaration.clone(),
+            closure: Rc::new(RefCell::new(environment)),
+            is_initializer: self.is_initializer,
+        }
+    }
+}
+
+impl fmt::Debug for LoxFunction {
+    // The closure may contain the function itself, so it is not printed
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "LoxFunction({})", self.name())
+    }
+}
+
+/// Host function callable from Lox. The line of a returned error is replaced with the line of the call
+pub type NativeFn = fn(&[Value]) -> Result<Value, RuntimeError>;
+
+#[derive(Debug)]
+pub struct NativeFunction {
+    pub name: String,
+    pub arity: usize,
+    pub function: NativeFn,
+}
+
+impl LoxCallable for LoxFunction {
+    fn arity(&self) -> usize {
+        LoxFunction::arity(self)
+    }
+
+    fn call(&self, interpreter: &mut Interpreter, arguments: Vec<Value>, line: u32) -> Result<Value, RuntimeError> {
+        interpreter.call_function(self, arguments, line)
+    }
+}
+
+impl LoxCallable for NativeFunction {
+    fn arity(&self) -> usize {
+        self.arity
+    }
+
+    fn call(&self, _interpreter: &mut Interpreter, arguments: Vec<Value>, line: u32) -> Result<Value, RuntimeError> {
+        (self.function)(&arguments).map_err(|error| {
+            let mut error = RuntimeError { line, ..error };
+            error.trace.push(StackFrame { function: self.name.clone(), line });
+            error
+        })
+    }
+}
@@ -0,0 +1,53 @@
+use std::collections::HashMap;
+
+/// Index of an interned string, equal symbols always stand for equal strings
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Symbol(u32);
+
+impl Symbol {
+    pub fn index(self) -> u32 {
+        self.0
+    }
+}
+
+/// Deduplicates names, every distinct string is stored once
+#[derive(Debug, Default)]
+pub struct StringInterner {
+    symbols: HashMap<String, Symbol>,
+    strings: Vec<String>,
+}
+
+impl StringInterner {
+    pub fn new() -> StringInterner {
+        StringInterner::default()
+    }
+
+    /// Returns the symbol of `name`, adding it on first use
+    pub fn intern(&mut self, name: &str) -> Symbol {
+        if let Some(&symbol) = self.symbols.get(name) {
+            return symbol;
+        }
+        let symbol = Symbol(self.strings.len() as u32);
+        self.strings.push(name.to_string());
+        self.symbols.insert(name.to_string(), symbol);
+        symbol
+    }
+
+    /// Symbol of an already interned `name`
+    pub fn get(&self, name: &str) -> Option<Symbol> {
+        self.symbols.get(name).copied()
+    }
+
+    /// `symbol` must come from this interner
+    pub fn resolve(&self, symbol: Symbol) -> &str {
+        &self.strings[symbol.0 as usize]
+    }
+
+    pub fn len(&self) -> usize {
+        self.strings.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.strings.is_empty()
+    }
+}
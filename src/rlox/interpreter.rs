@@ -0,0 +1,837 @@
+use super::ast::{Expr, ExprId, Identifier, LogicalOperator, Stmt};
+use super::class::{LoxClass, LoxInstance};
+use super::environment::Environment;
+use super::error_code::ErrorCode;
+use super::error_handler::{Diagnostic, ErrorHandler, Severity};
+use super::function::{LoxCallable, LoxFunction, NativeFn, NativeFunction};
+use super::natives;
+use super::value::Value;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::mem;
+use std::rc::Rc;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RuntimeError {
+    pub line: u32,
+    pub message: String,
+    pub code: ErrorCode,
+    // Calls the error came through, innermost first
+    pub trace: Vec<StackFrame>,
+}
+
+impl RuntimeError {
+    pub fn new(line: u32, code: ErrorCode, message: &str) -> RuntimeError {
+        RuntimeError { line, message: message.to_string(), code, trace: vec![] }
+    }
+
+    /// Function and line each frame was at when the error happened, innermost first and ending with
+    /// the top-level code as `<script>`. Empty for errors outside of functions
+    pub fn stack_trace(&self) -> Vec<(&str, u32)> {
+        let mut lines = vec![];
+        let mut line = self.line;
+        for frame in &self.trace {
+            lines.push((frame.function.as_str(), line));
+            line = frame.line;
+        }
+        if !self.trace.is_empty() {
+            lines.push(("<script>", line));
+        }
+        lines
+    }
+
+    /// Runtime errors only know their line, so the column is 0
+    pub fn diagnostic(&self) -> Diagnostic {
+        Diagnostic {
+            severity: Severity::Error,
+            code: self.code,
+            line: self.line,
+            column: 0,
+            length: 0,
+            position: String::new(),
+            message: self.message.clone(),
+        }
+    }
+}
+
+/// Call of `function` from `line`, that an error unwound
+#[derive(Debug, Clone, PartialEq)]
+pub struct StackFrame {
+    pub function: String,
+    pub line: u32,
+}
+
+/// Unwinds statement execution up to whoever handles it
+#[derive(Debug)]
+enum Signal {
+    Error(RuntimeError),
+    Return(Value),
+    // The parser only accepts `break` and `continue` inside loops, so the nearest loop always catches them
+    Break,
+    Continue,
+}
+
+impl From<RuntimeError> for Signal {
+    fn from(error: RuntimeError) -> Signal {
+        Signal::Error(error)
+    }
+}
+
+/// Deep enough for sane recursion while keeping the native stack from overflowing
+const MAX_CALL_DEPTH: usize = 1000;
+
+#[derive(Debug)]
+pub struct Interpreter {
+    globals: Rc<RefCell<Environment>>,
+    environment: Rc<RefCell<Environment>>,
+    // Scope distance of resolved local variables, unresolved ones are globals
+    locals: HashMap<ExprId, usize>,
+    call_depth: usize,
+}
+
+impl Interpreter {
+    pub fn new() -> Interpreter {
+        let mut interpreter = Interpreter::without_natives();
+        interpreter.define_natives();
+        interpreter
+    }
+
+    /// An interpreter with no globals at all, natives can be added with `define_native`
+    pub fn without_natives() -> Interpreter {
+        let globals = Rc::new(RefCell::new(Environment::new()));
+        Interpreter {
+            globals: globals.clone(),
+            environment: globals,
+            locals: HashMap::new(),
+            call_depth: 0,
+        }
+    }
+
+    /// Defines the standard natives: `clock`, `exit`, `chr`, `ord`, `str`, `num` and `doc`
+    pub fn define_natives(&mut self) {
+        self.define_native("clock", 0, natives::clock);
+        self.define_native("exit", 1, natives::exit);
+        self.define_native("chr", 1, natives::chr);
+        self.define_native("ord", 1, natives::ord);
+        self.define_native("str", 1, natives::str);
+        self.define_native("num", 1, natives::num);
+        self.define_native("doc", 1, natives::doc);
+    }
+
+    /// Makes a host function available to Lox programs as a global
+    pub fn define_native(&mut self, name: &str, arity: usize, function: NativeFn) {
+        let native = NativeFunction { name: name.to_string(), arity, function };
+        self.globals.borrow_mut().define(name, Value::NativeFunction(Rc::new(native)));
+    }
+
+    /// Records the scope distance of a variable reference, called by the resolver
+    pub fn resolve(&mut self, id: ExprId, depth: usize) {
+        self.locals.insert(id, depth);
+    }
+
+    /// Executes statements in order, stopping at the first runtime error
+    /// Returns `false` if execution was stopped by a runtime error
+    pub fn interpret(&mut self, statements: &[Stmt], error_handler: &dyn ErrorHandler) -> bool {
+        for statement in statements {
+            match self.execute(statement) {
+                Ok(()) => {}
+                Err(Signal::Error(error)) => {
+                    error_handler.runtime_error(&error);
+                    return false;
+                }
+                // The parser rejects top-level `return`, `break` and `continue`
+                Err(Signal::Return(_)) | Err(Signal::Break) | Err(Signal::Continue) => return true,
+            }
+        }
+        true
+    }
+
+    fn execute(&mut self, statement: &Stmt) -> Result<(), Signal> {
+        match statement {
+            Stmt::Expression(expr) => {
+                self.evaluate(expr)?;
+            }
+            Stmt::Print(expr) => {
+                println!("{}", self.evaluate(expr)?);
+            }
+            Stmt::Var { name, initializer, .. } => {
+                let value = match initializer {
+                    Some(initializer) => self.evaluate(initializer)?,
+                    None => Value::Nil,
+                };
+                self.environment.borrow_mut().define(&name.lexeme, value);
+            }
+            Stmt::If { condition, then_branch, else_branch } => {
+                if self.evaluate(condition)?.is_truthy() {
+                    self.execute(then_branch)?;
+                } else if let Some(else_branch) = else_branch {
+                    self.execute(else_branch)?;
+                }
+            }
+            Stmt::While { condition, body, increment } => {
+                while self.evaluate(condition)?.is_truthy() {
+                    match self.execute(body) {
+                        Err(Signal::Break) => break,
+                        Err(Signal::Continue) => {}
+                        result => result?,
+                    }
+                    if let Some(increment) = increment {
+                        self.evaluate(increment)?;
+                    }
+                }
+            }
+            Stmt::Function(declaration) => {
+                let function = LoxFunction::new(declaration.clone(), self.environment.clone());
+                self.environment.borrow_mut().define(&declaration.name.lexeme, Value::Function(Rc::new(function)));
+            }
+            Stmt::Return { value, .. } => {
+                let value = match value {
+                    Some(value) => self.evaluate(value)?,
+                    None => Value::Nil,
+                };
+                return Err(Signal::Return(value));
+            }
+            Stmt::Break => return Err(Signal::Break),
+            Stmt::Continue => return Err(Signal::Continue),
+            Stmt::Class { name, superclass, methods, doc } => {
+                let superclass = match superclass {
+                    Some(superclass) => match self.evaluate(superclass)? {
+                        Value::Class(class) => Some(class),
+                        _ => return Err(RuntimeError::new(name.line, ErrorCode::InvalidSuperclass, "Superclass must be a class.").into()),
+                    },
+                    None => None,
+                };
+
+                let closure = match &superclass {
+                    Some(superclass) => {
+                        let mut environment = Environment::with_enclosing(self.environment.clone());
+                        environment.define("super", Value::Class(superclass.clone()));
+                        Rc::new(RefCell::new(environment))
+                    }
+                    None => self.environment.clone(),
+                };
+                let methods = methods.iter()
+                    .map(|method| {
+                        let function = if method.name.lexeme == "init" {
+                            LoxFunction::initializer(method.clone(), closure.clone())
+                        } else {
+                            LoxFunction::new(method.clone(), closure.clone())
+                        };
+                        (method.name.lexeme.clone(), Rc::new(function))
+                    })
+                    .collect();
+                let class = LoxClass::new(&name.lexeme, superclass, methods, doc.clone());
+                self.environment.borrow_mut().define(&name.lexeme, Value::Class(Rc::new(class)));
+            }
+            Stmt::Block(statements) => {
+                let environment = Environment::with_enclosing(self.environment.clone());
+                self.execute_block(statements, Rc::new(RefCell::new(environment)))?;
+            }
+        }
+        Ok(())
+    }
+
+    fn look_up_variable(&self, name: &Identifier, id: ExprId) -> Result<Value, RuntimeError> {
+        match self.locals.get(&id) {
+            Some(&distance) => self.environment.borrow().get_at(distance, &name.lexeme, name.line),
+            None => self.globals.borrow().get(&name.lexeme, name.line),
+        }
+    }
+
+    /// Runs the body of `function`, the arity is already checked by the caller
+    pub(crate) fn call_function(&mut self, function: &LoxFunction, arguments: Vec<Value>, line: u32) -> Result<Value, RuntimeError> {
+        if self.call_depth >= MAX_CALL_DEPTH {
+            return Err(RuntimeError::new(line, ErrorCode::StackOverflow, "Stack overflow."));
+        }
+
+        let mut environment = Environment::with_enclosing(function.closure().clone());
+        for (param, argument) in function.declaration().params.iter().zip(arguments) {
+            environment.define(&param.lexeme, argument);
+        }
+
+        self.call_depth += 1;
+        let result = self.execute_block(&function.declaration().body, Rc::new(RefCell::new(environment)));
+        self.call_depth -= 1;
+
+        match result {
+            // `this` is bound right outside the body of a method
+            Ok(()) | Err(Signal::Return(_)) if function.is_initializer() => {
+                function.closure().borrow().get_at(0, "this", line)
+            }
+            Ok(()) => Ok(Value::Nil),
+            Err(Signal::Return(value)) => Ok(value),
+            Err(Signal::Error(mut error)) => {
+                error.trace.push(StackFrame { function: function.declaration().name.lexeme.clone(), line });
+                Err(error)
+            }
+            Err(Signal::Break) | Err(Signal::Continue) => Ok(Value::Nil),
+        }
+    }
+
+    /// Executes `statements` in `environment`, restoring the current one afterwards even on error
+    fn execute_block(&mut self, statements: &[Stmt], environment: Rc<RefCell<Environment>>) -> Result<(), Signal> {
+        let previous = mem::replace(&mut self.environment, environment);
+        let result = statements.iter().try_for_each(|statement| self.execute(statement));
+        self.environment = previous;
+        result
+    }
+
+    pub fn evaluate(&mut self, expr: &Expr) -> Result<Value, RuntimeError> {
+        match expr {
+            Expr::Literal(value) => Ok(value.clone()),
+            Expr::Grouping(expr) => self.evaluate(expr),
+            Expr::Logical { left, operator, right } => {
+                // Yields the operand itself rather than a boolean, the right one is evaluated only when needed
+                let left = self.evaluate(left)?;
+                let short_circuits = match operator {
+                    LogicalOperator::Or => left.is_truthy(),
+                    LogicalOperator::And => !left.is_truthy(),
+                };
+                if short_circuits {
+                    Ok(left)
+                } else {
+                    self.evaluate(right)
+                }
+            }
+            Expr::Unary { operator, right, line } => {
+                let right = self.evaluate(right)?;
+                Value::unary(*operator, right)
+                    .map_err(|message| RuntimeError::new(*line, ErrorCode::InvalidOperand, message))
+            }
+            Expr::Binary { left, operator, right, line } => {
+                let left = self.evaluate(left)?;
+                let right = self.evaluate(right)?;
+                Value::binary(*operator, left, right)
+                    .map_err(|message| RuntimeError::new(*line, ErrorCode::InvalidOperand, message))
+            }
+            Expr::Variable { name, id } => self.look_up_variable(name, *id),
+            Expr::This { keyword, id } => self.look_up_variable(keyword, *id),
+            Expr::Interpolation(parts) => {
+                let mut result = String::new();
+                for part in parts {
+                    result.push_str(&self.evaluate(part)?.to_string());
+                }
+                Ok(Value::Str(result))
+            }
+            Expr::Lambda(declaration) => {
+                let function = LoxFunction::new(declaration.clone(), self.environment.clone());
+                Ok(Value::Function(Rc::new(function)))
+            }
+            Expr::Super { keyword, method, id } => {
+                let distance = self.locals[id];
+                let environment = self.environment.borrow();
+                let superclass = environment.get_at(distance, "super", keyword.line)?;
+                // `this` is always bound right inside the scope holding `super`
+                let instance = environment.get_at(distance - 1, "this", keyword.line)?;
+
+                let found = match superclass {
+                    Value::Class(superclass) => superclass.find_method(&method.lexeme),
+                    _ => None,
+                };
+                match found {
+                    Some(found) => Ok(Value::Function(Rc::new(found.bind(instance)))),
+                    None => {
+                        let message = format!("Undefined property '{}'.", method.lexeme);
+                        Err(RuntimeError::new(method.line, ErrorCode::UndefinedProperty, &message))
+                    }
+                }
+            }
+            Expr::Assign { name, value, id } => {
+                let value = self.evaluate(value)?;
+                let (name, line) = (&name.lexeme, name.line);
+                match self.locals.get(id) {
+                    Some(&distance) => self.environment.borrow_mut().assign_at(distance, name, value.clone(), line)?,
+                    None => self.globals.borrow_mut().assign(name, value.clone(), line)?,
+                }
+                Ok(value)
+            }
+            Expr::Call { callee, arguments, line } => {
+                let callee = self.evaluate(callee)?;
+                let arguments = arguments.iter()
+                    .map(|argument| self.evaluate(argument))
+                    .collect::<Result<Vec<_>, _>>()?;
+
+                let callable: &dyn LoxCallable = match &callee {
+                    Value::Function(function) => function.as_ref(),
+                    Value::NativeFunction(function) => function.as_ref(),
+                    Value::Class(class) => class,
+                    _ => return Err(RuntimeError::new(*line, ErrorCode::NotCallable, "Can only call functions and classes.")),
+                };
+                if arguments.len() != callable.arity() {
+                    let message = format!("Expected {} arguments but got {}.", callable.arity(), arguments.len());
+                    return Err(RuntimeError::new(*line, ErrorCode::WrongArity, &message));
+                }
+                callable.call(self, arguments, *line)
+            }
+            Expr::Get { object, name } => match self.evaluate(object)? {
+                Value::Instance(instance) => LoxInstance::get(&instance, &name.lexeme).ok_or_else(|| {
+                    RuntimeError::new(name.line, ErrorCode::UndefinedProperty, &format!("Undefined property '{}'.", name.lexeme))
+                }),
+                _ => Err(RuntimeError::new(name.line, ErrorCode::NotAnInstance, "Only instances have properties.")),
+            },
+            Expr::Set { object, name, value } => match self.evaluate(object)? {
+                Value::Instance(instance) => {
+                    let value = self.evaluate(value)?;
+                    instance.borrow_mut().set(&name.lexeme, value.clone());
+                    Ok(value)
+                }
+                _ => Err(RuntimeError::new(name.line, ErrorCode::NotAnInstance, "Only instances have fields.")),
+            },
+        }
+    }
+}
+
+impl Default for Interpreter {
+    fn default() -> Interpreter {
+        Interpreter::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rlox::{CollectingErrorHandler, Parser, Resolver, Scanner, StringInterner};
+
+    fn evaluate(source: &str) -> Result<Value, RuntimeError> {
+        let error_handler = CollectingErrorHandler::new();
+        let mut scanner = Scanner::new(source, &error_handler);
+        let tokens = scanner.scan_tokens();
+        let expr = Parser::new(tokens, &error_handler).parse_expression().expect("an expression");
+        Interpreter::new().evaluate(&expr)
+    }
+
+    fn value(source: &str) -> Value {
+        evaluate(source).unwrap()
+    }
+
+    /// Runs `source` as a program, the interpreter is returned to look at the globals it left
+    fn run(source: &str) -> (Interpreter, Vec<Diagnostic>) {
+        let error_handler = CollectingErrorHandler::new();
+        let mut interpreter = Interpreter::new();
+        let mut scanner = Scanner::new(source, &error_handler);
+        let tokens = scanner.scan_tokens();
+        let statements = Parser::new(tokens, &error_handler).parse();
+        Resolver::new(&mut interpreter, &error_handler).resolve(&statements);
+        if !error_handler.had_errors() {
+            interpreter.interpret(&statements, &error_handler);
+        }
+        (interpreter, error_handler.take())
+    }
+
+    fn global(interpreter: &Interpreter, name: &str) -> Value {
+        interpreter.globals.borrow().get(name, 0).unwrap()
+    }
+
+    #[test]
+    fn arithmetic() {
+        assert_eq!(value("1 + 2"), Value::Number(3.0));
+        assert_eq!(value("5 - 7"), Value::Number(-2.0));
+        assert_eq!(value("3 * 4"), Value::Number(12.0));
+        assert_eq!(value("7 / 2"), Value::Number(3.5));
+        assert_eq!(value("-(1 + 2) * 2"), Value::Number(-6.0));
+        assert_eq!(value("\"con\" + \"cat\""), Value::Str("concat".to_string()));
+    }
+
+    #[test]
+    fn comparison_and_equality() {
+        assert_eq!(value("1 < 2"), Value::Bool(true));
+        assert_eq!(value("2 <= 2"), Value::Bool(true));
+        assert_eq!(value("1 > 2"), Value::Bool(false));
+        assert_eq!(value("1 >= 2"), Value::Bool(false));
+        assert_eq!(value("1 == 1"), Value::Bool(true));
+        assert_eq!(value("\"a\" != \"a\""), Value::Bool(false));
+        // Values of different types are never equal
+        assert_eq!(value("1 == \"1\""), Value::Bool(false));
+        assert_eq!(value("nil == nil"), Value::Bool(true));
+    }
+
+    #[test]
+    fn truthiness() {
+        assert_eq!(value("!nil"), Value::Bool(true));
+        assert_eq!(value("!false"), Value::Bool(true));
+        assert_eq!(value("!0"), Value::Bool(false));
+        assert_eq!(value("!\"\""), Value::Bool(false));
+    }
+
+    #[test]
+    fn logical_operators_short_circuit() {
+        // An assignment as the right operand is the side effect
+        let (interpreter, _) = run("var called = false; var a = true or (called = true); var b = false and (called = true);");
+        assert_eq!(global(&interpreter, "called"), Value::Bool(false));
+        assert_eq!(global(&interpreter, "a"), Value::Bool(true));
+        assert_eq!(global(&interpreter, "b"), Value::Bool(false));
+        // The right operand runs when the left one doesn't decide
+        let (interpreter, _) = run("var called = false; var a = false or (called = true);");
+        assert_eq!(global(&interpreter, "called"), Value::Bool(true));
+        assert_eq!(global(&interpreter, "a"), Value::Bool(true));
+    }
+
+    #[test]
+    fn logical_operators_return_an_operand() {
+        assert_eq!(value("nil or \"x\""), Value::Str("x".to_string()));
+        assert_eq!(value("\"a\" or \"b\""), Value::Str("a".to_string()));
+        assert_eq!(value("1 and 2"), Value::Number(2.0));
+        assert_eq!(value("nil and 2"), Value::Nil);
+    }
+
+    #[test]
+    fn arithmetic_errors() {
+        assert_eq!(evaluate("1 / 0").unwrap_err().message, "Division by zero.");
+        assert_eq!(evaluate("1 + \"x\"").unwrap_err().message, "Operands must be two numbers or two strings.");
+        assert_eq!(evaluate("1 < \"x\"").unwrap_err().message, "Operands must be numbers.");
+        assert_eq!(evaluate("-\"x\"").unwrap_err().message, "Operand must be a number.");
+        assert_eq!(evaluate("\n\n1 * nil").unwrap_err().line, 3);
+    }
+
+    #[test]
+    fn variables() {
+        let (interpreter, diagnostics) = run("var a = 1; var b; var c = a + 2;");
+        assert_eq!(diagnostics, vec![]);
+        assert_eq!(global(&interpreter, "a"), Value::Number(1.0));
+        assert_eq!(global(&interpreter, "b"), Value::Nil);
+        assert_eq!(global(&interpreter, "c"), Value::Number(3.0));
+    }
+
+    #[test]
+    fn undefined_variable() {
+        let (_, diagnostics) = run("var a = 1;\nprint a + b;");
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].code, ErrorCode::UndefinedVariable);
+        assert_eq!(diagnostics[0].message, "Undefined variable 'b'.");
+        assert_eq!(diagnostics[0].line, 2);
+    }
+
+    #[test]
+    fn block_scope() {
+        // Shadowing inside a block leaves the outer binding alone
+        let (interpreter, _) = run("var a = \"outer\"; var inner; { var a = \"inner\"; inner = a; }");
+        assert_eq!(global(&interpreter, "a"), Value::Str("outer".to_string()));
+        assert_eq!(global(&interpreter, "inner"), Value::Str("inner".to_string()));
+        // Assigning an outer variable from a block changes it
+        let (interpreter, _) = run("var a = 1; { a = 2; }");
+        assert_eq!(global(&interpreter, "a"), Value::Number(2.0));
+    }
+
+    #[test]
+    fn variable_not_visible_after_its_block() {
+        let (_, diagnostics) = run("{ var a = 1; print a; }\nprint a;");
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!((diagnostics[0].code, diagnostics[0].line), (ErrorCode::UndefinedVariable, 2));
+    }
+
+    #[test]
+    fn if_else() {
+        let (interpreter, _) = run("var a = \"\"; if (true) a = \"then\"; else a = \"else\";");
+        assert_eq!(global(&interpreter, "a"), Value::Str("then".to_string()));
+        let (interpreter, _) = run("var a = \"\"; if (nil) a = \"then\"; else a = \"else\";");
+        assert_eq!(global(&interpreter, "a"), Value::Str("else".to_string()));
+        let (interpreter, _) = run("var a = \"unchanged\"; if (false) a = \"then\";");
+        assert_eq!(global(&interpreter, "a"), Value::Str("unchanged".to_string()));
+    }
+
+    #[test]
+    fn else_if_chain() {
+        let source = "var n = 2; var a; if (n == 1) a = \"one\"; else if (n == 2) a = \"two\"; else a = \"many\";";
+        assert_eq!(global(&run(source).0, "a"), Value::Str("two".to_string()));
+        // The dangling else belongs to the nearest if
+        let source = "var a = \"unchanged\"; if (false) if (true) a = \"inner\"; else a = \"else\";";
+        assert_eq!(global(&run(source).0, "a"), Value::Str("unchanged".to_string()));
+    }
+
+    #[test]
+    fn if_condition_needs_parentheses() {
+        let (_, diagnostics) = run("if (true print 1;");
+        assert_eq!(diagnostics[0].message, "Expect ')' after if condition.");
+    }
+
+    #[test]
+    fn for_loop_runs_like_while() {
+        // The digits of `out` are the values of `i` in order
+        let for_loop = run("var out = 0; for (var i = 0; i < 5; i = i + 1) out = out * 10 + i;").0;
+        let while_loop = run("var out = 0; { var i = 0; while (i < 5) { out = out * 10 + i; i = i + 1; } }").0;
+        assert_eq!(global(&for_loop, "out"), global(&while_loop, "out"));
+        assert_eq!(global(&for_loop, "out"), Value::Number(1234.0));
+    }
+
+    #[test]
+    fn for_loop_clauses_are_optional() {
+        // An expression initializer, the variable outlives the loop
+        let (interpreter, _) = run("var i; for (i = 0; i < 3; i = i + 1) {}");
+        assert_eq!(global(&interpreter, "i"), Value::Number(3.0));
+        // Only a condition
+        let (interpreter, _) = run("var n = 0; for (; n < 3;) n = n + 1;");
+        assert_eq!(global(&interpreter, "n"), Value::Number(3.0));
+    }
+
+    #[test]
+    fn break_leaves_a_counting_loop_early() {
+        let (interpreter, _) = run("var i = 0; var runs = 0; while (i < 10) { if (i == 3) break; runs = runs + 1; i = i + 1; }");
+        assert_eq!(global(&interpreter, "i"), Value::Number(3.0));
+        assert_eq!(global(&interpreter, "runs"), Value::Number(3.0));
+        // Only the innermost loop is left
+        let source = "var n = 0; for (var i = 0; i < 3; i = i + 1) { while (true) break; n = n + 1; }";
+        assert_eq!(global(&run(source).0, "n"), Value::Number(3.0));
+        // A `for` without a condition loops until `break`
+        let (interpreter, _) = run("var n = 0; for (;;) { n = n + 1; if (n == 3) break; }");
+        assert_eq!(global(&interpreter, "n"), Value::Number(3.0));
+    }
+
+    #[test]
+    fn break_outside_loop() {
+        let (_, diagnostics) = run("break;");
+        assert_eq!(diagnostics[0].code, ErrorCode::OutsideLoop);
+        assert_eq!(diagnostics[0].message, "Can't break outside a loop.");
+    }
+
+    #[test]
+    fn continue_in_while() {
+        let source = "var out = 0; var i = 0; while (i < 5) { i = i + 1; if (i == 2) continue; out = out * 10 + i; }";
+        assert_eq!(global(&run(source).0, "out"), Value::Number(1345.0));
+    }
+
+    #[test]
+    fn continue_in_for_runs_the_increment() {
+        // The digits of `out` are the values of `i` the body got to the end with
+        let source = "var out = 0; for (var i = 0; i < 5; i = i + 1) { if (i == 2) continue; out = out * 10 + i; }";
+        assert_eq!(global(&run(source).0, "out"), Value::Number(134.0));
+    }
+
+    #[test]
+    fn continue_outside_loop() {
+        let (_, diagnostics) = run("continue;");
+        assert_eq!(diagnostics[0].code, ErrorCode::OutsideLoop);
+        // A function body inside a loop is outside of it
+        let (_, diagnostics) = run("while (true) { fun f() { continue; } }");
+        assert_eq!(diagnostics[0].message, "Can't continue outside a loop.");
+    }
+
+    #[test]
+    fn recursive_function() {
+        let (interpreter, diagnostics) = run("var f = 1; fun fact(n) { if (n > 1) { f = f * n; fact(n - 1); } }\nfact(10);");
+        assert_eq!(diagnostics, vec![]);
+        assert_eq!(global(&interpreter, "f"), Value::Number(3628800.0));
+    }
+
+    #[test]
+    fn function_arity() {
+        let (_, diagnostics) = run("fun add(a, b) { print a + b; }\nadd(1, 2, 3);");
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].code, ErrorCode::WrongArity);
+        assert_eq!(diagnostics[0].message, "Expected 2 arguments but got 3.");
+        assert_eq!(diagnostics[0].line, 2);
+    }
+
+    #[test]
+    fn closure_captures_its_environment() {
+        let source = "var inc; var out; fun counter() { var n = 0; fun count() { n = n + 1; out = n; } inc = count; }\n\
+            counter(); var c = inc; c(); c(); var second = out; counter(); inc(); var fresh = out;";
+        let (interpreter, _) = run(source);
+        assert_eq!(global(&interpreter, "second"), Value::Number(2.0));
+        // Each call of `counter` has its own `n`
+        assert_eq!(global(&interpreter, "fresh"), Value::Number(1.0));
+    }
+
+    #[test]
+    fn recursive_return() {
+        let (interpreter, diagnostics) = run("fun fact(n) { if (n <= 1) return 1; return n * fact(n - 1); }\nvar f = fact(10);");
+        assert_eq!(diagnostics, vec![]);
+        assert_eq!(global(&interpreter, "f"), Value::Number(3628800.0));
+    }
+
+    #[test]
+    fn early_return_from_loops() {
+        let source = "fun find() { var i = 0; while (true) { if (i == 3) return i; i = i + 1; } }\n\
+            fun first() { for (var i = 0; i < 10; i = i + 1) if (i == 4) return i; return nil; }\n\
+            var a = find(); var b = first();";
+        let (interpreter, _) = run(source);
+        assert_eq!(global(&interpreter, "a"), Value::Number(3.0));
+        assert_eq!(global(&interpreter, "b"), Value::Number(4.0));
+    }
+
+    #[test]
+    fn bare_return_gives_nil() {
+        let (interpreter, _) = run("var a = 1; fun f() { return; a = 2; } var r = f();");
+        assert_eq!(global(&interpreter, "r"), Value::Nil);
+        assert_eq!(global(&interpreter, "a"), Value::Number(1.0));
+        let (interpreter, _) = run("fun f() {} var r = f();");
+        assert_eq!(global(&interpreter, "r"), Value::Nil);
+    }
+
+    #[test]
+    fn top_level_return() {
+        let (_, diagnostics) = run("return 1;");
+        assert_eq!(diagnostics[0].code, ErrorCode::TopLevelReturn);
+        assert_eq!(diagnostics[0].message, "Can't return from top-level code.");
+    }
+
+    #[test]
+    fn lambda_argument() {
+        let (interpreter, diagnostics) = run("fun twice(f) { f(); f(); }\nvar n = 0; twice(fun () { n = n + 1; });");
+        assert_eq!(diagnostics, vec![]);
+        assert_eq!(global(&interpreter, "n"), Value::Number(2.0));
+        let (interpreter, _) = run("var square = fun (x) { return x * x; }; var r = square(4);");
+        assert_eq!(global(&interpreter, "r"), Value::Number(16.0));
+    }
+
+    #[test]
+    fn clock_native() {
+        assert_eq!(value("clock() > 0"), Value::Bool(true));
+    }
+
+    #[test]
+    fn conversion_natives() {
+        assert_eq!(value("chr(65)"), Value::Str("A".to_string()));
+        assert_eq!(value("ord(\"é\")"), Value::Number(233.0));
+        assert_eq!(value("str(1.5)"), Value::Str("1.5".to_string()));
+        assert_eq!(value("str(nil) + str(true)"), Value::Str("niltrue".to_string()));
+        assert_eq!(value("num(\" 42 \")"), Value::Number(42.0));
+        assert_eq!(value("num(7)"), Value::Number(7.0));
+    }
+
+    #[test]
+    fn native_errors() {
+        assert_eq!(evaluate("num(\"abc\")").unwrap_err().message, "Can't convert 'abc' to a number.");
+        assert_eq!(evaluate("num(nil)").unwrap_err().message, "Argument must be a number or a string.");
+        assert_eq!(evaluate("chr(-1)").unwrap_err().message, "Invalid code point '-1'.");
+        assert_eq!(evaluate("chr(1.5)").unwrap_err().message, "Invalid code point '1.5'.");
+        assert_eq!(evaluate("ord(\"ab\")").unwrap_err().message, "Argument must be a single character string.");
+        assert_eq!(evaluate("exit(\"now\")").unwrap_err().message, "Exit code must be an integer.");
+        assert_eq!(evaluate("str(1, 2)").unwrap_err().message, "Expected 1 arguments but got 2.");
+        // Errors of natives are reported at the line of the call
+        assert_eq!(evaluate("\n\nnum(\"abc\")").unwrap_err().line, 3);
+        assert_eq!(evaluate("num(nil)").unwrap_err().code, ErrorCode::InvalidArgument);
+        assert_eq!(evaluate("str(1, 2)").unwrap_err().code, ErrorCode::WrongArity);
+    }
+
+    #[test]
+    fn interpolation() {
+        assert_eq!(value("\"a ${1 + 2} b ${\"c\" + \"${nil}\"}\""), Value::Str("a 3 b cnil".to_string()));
+        // A string after the expression isn't taken for the rest of the interpolated one
+        let (_, diagnostics) = run("print \"${1 \"b\"}\";");
+        assert_eq!((diagnostics[0].column, diagnostics[0].message.as_str()), (12, "Expect '}' after interpolated expression."));
+    }
+
+    #[test]
+    fn initializer() {
+        let source = "class Point { init(x, y) { this.x = x; this.y = y; } }\n\
+            var p = Point(1, 2); var sum = p.x + p.y; var again = p.init(3, 4) == p; var moved = p.x;";
+        let (interpreter, diagnostics) = run(source);
+        assert_eq!(diagnostics, vec![]);
+        assert_eq!(global(&interpreter, "sum"), Value::Number(3.0));
+        // Calling `init` directly runs it again and gives back the instance
+        assert_eq!(global(&interpreter, "again"), Value::Bool(true));
+        assert_eq!(global(&interpreter, "moved"), Value::Number(3.0));
+    }
+
+    #[test]
+    fn initializer_errors() {
+        // The class takes the arguments of `init`
+        let (_, diagnostics) = run("class Point { init(x) {} }\nPoint();");
+        assert_eq!(diagnostics[0].code, ErrorCode::WrongArity);
+        assert_eq!(diagnostics[0].message, "Expected 1 arguments but got 0.");
+
+        let (_, diagnostics) = run("class Point { init() { return 1; } }");
+        assert_eq!(diagnostics[0].code, ErrorCode::InitializerReturnsValue);
+        assert_eq!(diagnostics[0].message, "Can't return a value from an initializer.");
+
+        let (_, diagnostics) = run("class Point {}\nprint Point().x;");
+        assert_eq!(diagnostics[0].code, ErrorCode::UndefinedProperty);
+        assert_eq!(diagnostics[0].message, "Undefined property 'x'.");
+    }
+
+    #[test]
+    fn method_reads_this_field() {
+        let source = "class Box { get() { return this.field; } }\nvar b = Box(); b.field = 42; var r = b.get();";
+        let (interpreter, diagnostics) = run(source);
+        assert_eq!(diagnostics, vec![]);
+        assert_eq!(global(&interpreter, "r"), Value::Number(42.0));
+        // A method taken off its instance keeps `this`
+        let source = "class Box { get() { return this.field; } }\nvar b = Box(); b.field = 1; var get = b.get; var r = get();";
+        let (interpreter, _) = run(source);
+        assert_eq!(global(&interpreter, "r"), Value::Number(1.0));
+    }
+
+    #[test]
+    fn this_outside_class() {
+        for source in &["print this;", "fun f() { return this; }"] {
+            let (_, diagnostics) = run(source);
+            assert_eq!(diagnostics[0].code, ErrorCode::ThisOutsideClass, "{}", source);
+            assert_eq!(diagnostics[0].message, "Can't use 'this' outside of a class.");
+        }
+    }
+
+    #[test]
+    fn inheritance_and_super() {
+        let source = "class A { name() { return \"A\"; } greet() { return \"hi \" + this.name(); } }\n\
+            class B < A { name() { return \"B\"; } }\n\
+            class C < B { name() { return \"C\" + super.name(); } }\n\
+            var inherited = B().greet(); var overridden = B().name(); var chained = C().name(); var bound = C().greet();";
+        let (interpreter, diagnostics) = run(source);
+        assert_eq!(diagnostics, vec![]);
+        assert_eq!(global(&interpreter, "inherited"), Value::Str("hi B".to_string()));
+        assert_eq!(global(&interpreter, "overridden"), Value::Str("B".to_string()));
+        assert_eq!(global(&interpreter, "chained"), Value::Str("CB".to_string()));
+        assert_eq!(global(&interpreter, "bound"), Value::Str("hi CB".to_string()));
+    }
+
+    #[test]
+    fn super_binds_this() {
+        let source = "class A { describe() { return this.label; } }\n\
+            class B < A { describe() { return \"B:\" + super.describe(); } }\n\
+            var b = B(); b.label = \"mine\"; var r = b.describe();";
+        assert_eq!(global(&run(source).0, "r"), Value::Str("B:mine".to_string()));
+    }
+
+    #[test]
+    fn invalid_superclasses() {
+        let (_, diagnostics) = run("class A < A {}");
+        assert_eq!(diagnostics[0].code, ErrorCode::InheritsFromItself);
+        assert_eq!(diagnostics[0].message, "A class can't inherit from itself.");
+
+        let (_, diagnostics) = run("var X = 1;\nclass B < X {}");
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!((diagnostics[0].code, diagnostics[0].line), (ErrorCode::InvalidSuperclass, 2));
+        assert_eq!(diagnostics[0].message, "Superclass must be a class.");
+    }
+
+    #[test]
+    fn resolver_shares_the_scanner_interner() {
+        let source = "var out;\n\
+            class A { init(x) { this.x = x; } get() { var x = this.x; { var y = x; x = y + 1; } return x; } }\n\
+            { var a = 1; { var a = 10; } fun f(n) { return n + a; } out = f(A(2).get()); }";
+        let (expected, expected_diagnostics) = run(source);
+        assert_eq!(global(&expected, "out"), Value::Number(4.0));
+
+        let error_handler = CollectingErrorHandler::new();
+        let interner = Rc::new(RefCell::new(StringInterner::new()));
+        let mut interpreter = Interpreter::new();
+        let mut scanner = Scanner::with_interner(source, &error_handler, interner.clone());
+        let tokens = scanner.scan_tokens();
+        let statements = Parser::new(tokens, &error_handler).parse();
+        Resolver::new(&mut interpreter, &error_handler).sharing_interner(interner).resolve(&statements);
+        interpreter.interpret(&statements, &error_handler);
+
+        assert_eq!(global(&interpreter, "out"), Value::Number(4.0));
+        // Such as the shadowing of `a`
+        assert_eq!(error_handler.take(), expected_diagnostics);
+    }
+
+    #[test]
+    fn stack_trace_is_innermost_first() {
+        let source = "fun inner() {\n  return nil + 1;\n}\nfun outer() {\n  inner();\n}\nouter();";
+        let error_handler = CollectingErrorHandler::new();
+        let mut interpreter = Interpreter::new();
+        let mut scanner = Scanner::new(source, &error_handler);
+        let tokens = scanner.scan_tokens();
+        let statements = Parser::new(tokens, &error_handler).parse();
+        Resolver::new(&mut interpreter, &error_handler).resolve(&statements);
+        let error = statements.iter()
+            .find_map(|statement| match interpreter.execute(statement) {
+                Err(Signal::Error(error)) => Some(error),
+                _ => None,
+            })
+            .expect("a runtime error");
+        assert_eq!(error.stack_trace(), vec![("inner", 2), ("outer", 5), ("<script>", 7)]);
+        assert!(RuntimeError::new(1, error.code, "").stack_trace().is_empty());
+    }
+}
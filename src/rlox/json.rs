@@ -0,0 +1,163 @@
+use super::error_handler::{Diagnostic, Severity};
+use super::scanner::{Token, TokenInfo};
+use super::value::format_number;
+
+/// JSON array with an object per token: `kind`, `lexeme`, `line`, `column`, the byte `span`
+/// and `value` for literals. `source` must be the code the tokens were scanned from
+pub fn tokens_to_json(tokens: &[TokenInfo], source: &str) -> String {
+    let objects: Vec<String> = tokens.iter()
+        .map(|token| {
+            // The variant name without its payload
+            let kind = format!("{:?}", token.token());
+            let kind = kind.split('(').next().unwrap();
+            let value = match token.token() {
+                // JSON has no infinity, huge literals become null
+                Token::NumberValue(number) if number.is_finite() => format!(", \"value\": {}", format_number(*number)),
+                Token::NumberValue(_) => ", \"value\": null".to_string(),
+                Token::StringValue(string) | Token::Interpolation(string) => {
+                    format!(", \"value\": {}", json_string(string))
+                }
+                _ => String::new(),
+            };
+            let span = token.span();
+            format!(
+                "  {{\"kind\": {}, \"lexeme\": {}, \"line\": {}, \"column\": {}, \"span\": [{}, {}]{}}}",
+                json_string(kind),
+                json_string(token.lexeme(source)),
+                token.line(),
+                token.column(),
+                span.start,
+                span.end,
+                value
+            )
+        })
+        .collect();
+    format!("[\n{}\n]", objects.join(",\n"))
+}
+
+/// JSON object with the `code`, `severity`, `message`, `file`, `line`, `column` and byte `span` of the diagnostic.
+/// Unknown parts are null, the span needs the `source` the diagnostic is about
+pub fn diagnostic_to_json(diagnostic: &Diagnostic, file: Option<&str>, source: Option<&str>) -> String {
+    let severity = match diagnostic.severity {
+        Severity::Error => "error",
+        Severity::Warning => "warning",
+    };
+    let file = file.map_or("null".to_string(), json_string);
+    // Runtime errors only know their line
+    let column = if diagnostic.column == 0 { "null".to_string() } else { diagnostic.column.to_string() };
+    let span = match source.and_then(|source| line_offset(source, diagnostic.line)) {
+        Some(offset) if diagnostic.column > 0 => {
+            let start = offset + diagnostic.column as usize - 1;
+            format!("[{}, {}]", start, start + diagnostic.length)
+        }
+        _ => "null".to_string(),
+    };
+    format!(
+        "{{\"code\": {}, \"severity\": \"{}\", \"message\": {}, \"file\": {}, \"line\": {}, \"column\": {}, \"span\": {}}}",
+        json_string(diagnostic.code.as_str()),
+        severity,
+        json_string(&diagnostic.message),
+        file,
+        diagnostic.line,
+        column,
+        span
+    )
+}
+
+/// Byte offset of the 1-based `line` in `source`
+fn line_offset(source: &str, line: u32) -> Option<usize> {
+    let previous_lines = line.checked_sub(1)? as usize;
+    if previous_lines == 0 {
+        return Some(0);
+    }
+    source.match_indices('\n').nth(previous_lines - 1).map(|(offset, _)| offset + 1)
+}
+
+pub(crate) fn json_string(text: &str) -> String {
+    let mut result = String::with_capacity(text.len() + 2);
+    result.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => result.push_str("\\\""),
+            '\\' => result.push_str("\\\\"),
+            '\n' => result.push_str("\\n"),
+            '\r' => result.push_str("\\r"),
+            '\t' => result.push_str("\\t"),
+            c if (c as u32) < 0x20 => result.push_str(&format!("\\u{:04x}", c as u32)),
+            c => result.push(c),
+        }
+    }
+    result.push('"');
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rlox::error_code::ErrorCode;
+    use crate::rlox::scan;
+
+    #[test]
+    fn tokens() {
+        let source = r#"print "a\"b" + 1.5;"#;
+        let expected = [
+            r#"["#,
+            r#"  {"kind": "Print", "lexeme": "print", "line": 1, "column": 1, "span": [0, 5]},"#,
+            r#"  {"kind": "StringValue", "lexeme": "\"a\\\"b\"", "line": 1, "column": 7, "span": [6, 12], "value": "a\"b"},"#,
+            r#"  {"kind": "Plus", "lexeme": "+", "line": 1, "column": 14, "span": [13, 14]},"#,
+            r#"  {"kind": "NumberValue", "lexeme": "1.5", "line": 1, "column": 16, "span": [15, 18], "value": 1.5},"#,
+            r#"  {"kind": "Semicolon", "lexeme": ";", "line": 1, "column": 19, "span": [18, 19]},"#,
+            r#"  {"kind": "EOF", "lexeme": "", "line": 1, "column": 20, "span": [19, 19]}"#,
+            r#"]"#,
+        ];
+        assert_eq!(tokens_to_json(&scan(source).unwrap(), source), expected.join("\n"));
+    }
+
+    #[test]
+    fn literal_values() {
+        let source = r#"1e400 "${x}""#;
+        let json = tokens_to_json(&scan(source).unwrap(), source);
+        let lines: Vec<&str> = json.lines().collect();
+        // JSON has no infinity
+        assert!(lines[1].ends_with(r#""lexeme": "1e400", "line": 1, "column": 1, "span": [0, 5], "value": null},"#), "{}", json);
+        assert!(lines[2].ends_with(r#""lexeme": "\"${", "line": 1, "column": 7, "span": [6, 9], "value": ""},"#), "{}", json);
+    }
+
+    #[test]
+    fn diagnostics() {
+        let mut diagnostic = Diagnostic {
+            severity: Severity::Warning,
+            code: ErrorCode::UnusedVariable,
+            line: 2,
+            column: 5,
+            length: 1,
+            position: " at 'a'".to_string(),
+            message: "Unused variable 'a'.".to_string(),
+        };
+        assert_eq!(
+            diagnostic_to_json(&diagnostic, Some("main.lox"), Some("{\n    var a = 1;\n}")),
+            concat!(
+                r#"{"code": "W0001", "severity": "warning", "message": "Unused variable 'a'.", "#,
+                r#""file": "main.lox", "line": 2, "column": 5, "span": [6, 7]}"#
+            )
+        );
+
+        // Runtime errors know only their line
+        diagnostic.severity = Severity::Error;
+        diagnostic.code = ErrorCode::UndefinedVariable;
+        diagnostic.column = 0;
+        diagnostic.message = "Undefined variable 'a'.".to_string();
+        assert_eq!(
+            diagnostic_to_json(&diagnostic, None, Some("{\n    print a;\n}")),
+            concat!(
+                r#"{"code": "E0102", "severity": "error", "message": "Undefined variable 'a'.", "#,
+                r#""file": null, "line": 2, "column": null, "span": null}"#
+            )
+        );
+    }
+
+    #[test]
+    fn strings_are_escaped() {
+        assert_eq!(json_string("tab\there \"quoted\" \\ \u{1}"), r#""tab\there \"quoted\" \\ \u0001""#);
+    }
+}
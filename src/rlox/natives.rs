@@ -0,0 +1,69 @@
+use super::error_code::ErrorCode;
+use super::interpreter::RuntimeError;
+use super::value::Value;
+use std::process;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Seconds since the Unix epoch
+pub fn clock(_arguments: &[Value]) -> Result<Value, RuntimeError> {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH)
+        .map_err(|err| RuntimeError::new(0, ErrorCode::NativeFailure, &err.to_string()))?;
+    Ok(Value::Number(now.as_secs_f64()))
+}
+
+/// Ends the process with the given status code
+pub fn exit(arguments: &[Value]) -> Result<Value, RuntimeError> {
+    match arguments[0] {
+        Value::Number(code) if code.fract() == 0.0 => process::exit(code as i32),
+        _ => Err(RuntimeError::new(0, ErrorCode::InvalidArgument, "Exit code must be an integer.")),
+    }
+}
+
+/// One char string of a Unicode code point
+pub fn chr(arguments: &[Value]) -> Result<Value, RuntimeError> {
+    let c = match arguments[0] {
+        Value::Number(code) if code.fract() == 0.0 && code >= 0.0 => char::from_u32(code as u32),
+        _ => None,
+    };
+    match c {
+        Some(c) => Ok(Value::Str(c.to_string())),
+        None => Err(RuntimeError::new(0, ErrorCode::InvalidArgument, &format!("Invalid code point '{}'.", arguments[0]))),
+    }
+}
+
+/// Code point of a one char string
+pub fn ord(arguments: &[Value]) -> Result<Value, RuntimeError> {
+    if let Value::Str(string) = &arguments[0] {
+        let mut chars = string.chars();
+        if let (Some(c), None) = (chars.next(), chars.next()) {
+            return Ok(Value::Number(c as u32 as f64));
+        }
+    }
+    Err(RuntimeError::new(0, ErrorCode::InvalidArgument, "Argument must be a single character string."))
+}
+
+/// Any value as the string `print` would show
+pub fn str(arguments: &[Value]) -> Result<Value, RuntimeError> {
+    Ok(Value::Str(arguments[0].to_string()))
+}
+
+/// Doc comment of a function or class, `nil` when it has none
+pub fn doc(arguments: &[Value]) -> Result<Value, RuntimeError> {
+    let doc = match &arguments[0] {
+        Value::Function(function) => function.declaration().doc.clone(),
+        Value::Class(class) => class.doc().map(str::to_string),
+        _ => None,
+    };
+    Ok(doc.map_or(Value::Nil, Value::Str))
+}
+
+/// Number from its text, surrounding whitespace is ignored
+pub fn num(arguments: &[Value]) -> Result<Value, RuntimeError> {
+    match &arguments[0] {
+        Value::Number(number) => Ok(Value::Number(*number)),
+        Value::Str(string) => string.trim().parse::<f64>()
+            .map(Value::Number)
+            .map_err(|_| RuntimeError::new(0, ErrorCode::InvalidArgument, &format!("Can't convert '{}' to a number.", string))),
+        _ => Err(RuntimeError::new(0, ErrorCode::InvalidArgument, "Argument must be a number or a string.")),
+    }
+}
@@ -0,0 +1,614 @@
+use super::ast::{BinaryOperator, Expr, ExprId, FunctionDecl, Identifier, LogicalOperator, Stmt, UnaryOperator};
+use super::error_code::ErrorCode;
+use super::error_handler::{Diagnostic, ErrorHandler, Severity};
+use super::scanner::{Token, TokenInfo};
+use super::value::Value;
+use std::rc::Rc;
+
+const MAX_ARGUMENTS: usize = 255;
+
+/// Signals that a syntax error was already reported and the parser has to synchronize
+#[derive(Debug)]
+struct ParseError;
+
+type ParseResult<T> = Result<T, ParseError>;
+
+#[derive(Debug)]
+pub struct Parser<'t, 's> {
+    tokens: &'t [TokenInfo<'s>],
+    error_handler: &'t dyn ErrorHandler,
+    current: usize,
+    had_errors: bool,
+    // Number of function bodies enclosing the current token
+    function_depth: usize,
+    // Number of loops enclosing the current token inside the current function
+    loop_depth: usize,
+    // Accept a trailing expression without `;` and print its value
+    repl: bool,
+}
+
+impl<'t, 's> Parser<'t, 's> {
+    /// `tokens` must be terminated by `EOF`, as produced by the scanner
+    pub fn new(tokens: &'t [TokenInfo<'s>], error_handler: &'t dyn ErrorHandler) -> Parser<'t, 's> {
+        Parser {
+            tokens,
+            error_handler,
+            current: 0,
+            had_errors: false,
+            function_depth: 0,
+            loop_depth: 0,
+            repl: false,
+        }
+    }
+
+    /// Parses the whole program, statements with syntax errors are reported and skipped
+    pub fn parse(&mut self) -> Vec<Stmt> {
+        let mut statements = vec![];
+        while !self.is_at_end() {
+            if let Some(statement) = self.declaration() {
+                statements.push(statement);
+            }
+        }
+        statements
+    }
+
+    /// Same as `parse`, but an expression ending the input without `;` becomes a print statement
+    pub fn parse_repl(&mut self) -> Vec<Stmt> {
+        self.repl = true;
+        self.parse()
+    }
+
+    pub fn had_errors(&self) -> bool {
+        self.had_errors
+    }
+
+    pub fn parse_expression(&mut self) -> Option<Expr> {
+        match self.expression() {
+            Ok(expr) => Some(expr),
+            Err(ParseError) => {
+                self.synchronize();
+                None
+            }
+        }
+    }
+
+    /// Declaration or statement, on syntax error synchronizes and returns `None`
+    fn declaration(&mut self) -> Option<Stmt> {
+        let doc = self.peek().doc().map(str::to_string);
+        let result = if self.matches(&[Token::Class]) {
+            self.class_declaration(doc)
+        } else if self.check(&Token::Fun) && matches!(self.peek_next().token(), Token::Identifier(_)) {
+            // Otherwise `fun` starts a lambda in an expression statement
+            self.advance();
+            self.function("function", doc).map(Stmt::Function)
+        } else if self.matches(&[Token::Var]) {
+            self.var_declaration(doc)
+        } else {
+            self.statement()
+        };
+
+        match result {
+            Ok(statement) => Some(statement),
+            Err(ParseError) => {
+                self.synchronize();
+                None
+            }
+        }
+    }
+
+    fn class_declaration(&mut self, doc: Option<String>) -> ParseResult<Stmt> {
+        let name = self.consume_identifier("Expect class name.")?;
+
+        let superclass = if self.matches(&[Token::Less]) {
+            let name = self.consume_identifier("Expect superclass name.")?;
+            Some(Expr::Variable { name, id: ExprId::unique() })
+        } else {
+            None
+        };
+
+        self.consume(Token::LeftBrace, "Expect '{' before class body.")?;
+
+        let mut methods = vec![];
+        while !self.check(&Token::RightBrace) && !self.is_at_end() {
+            let doc = self.peek().doc().map(str::to_string);
+            methods.push(self.function("method", doc)?);
+        }
+        self.consume(Token::RightBrace, "Expect '}' after class body.")?;
+
+        Ok(Stmt::Class { name, superclass, methods, doc })
+    }
+
+    /// Function or method declaration starting at its name, `kind` names what is declared in error messages
+    fn function(&mut self, kind: &str, doc: Option<String>) -> ParseResult<Rc<FunctionDecl>> {
+        let name = self.consume_identifier(&format!("Expect {} name.", kind))?;
+        self.consume(Token::LeftParen, &format!("Expect '(' after {} name.", kind))?;
+        self.function_rest(kind, name, doc)
+    }
+
+    /// Parameters and body of a function, starting after the `(`
+    fn function_rest(&mut self, kind: &str, name: Identifier, doc: Option<String>) -> ParseResult<Rc<FunctionDecl>> {
+        let mut params = vec![];
+        if !self.check(&Token::RightParen) {
+            loop {
+                if params.len() >= MAX_ARGUMENTS {
+                    self.error(self.peek(), ErrorCode::TooManyArguments, &format!("Can't have more than {} parameters.", MAX_ARGUMENTS));
+                }
+                params.push(self.consume_identifier("Expect parameter name.")?);
+
+                if !self.matches(&[Token::Comma]) {
+                    break;
+                }
+            }
+        }
+        self.consume(Token::RightParen, "Expect ')' after parameters.")?;
+
+        self.consume(Token::LeftBrace, &format!("Expect '{{' before {} body.", kind))?;
+        // A loop around the declaration doesn't enclose the body
+        let enclosing_loop_depth = self.loop_depth;
+        self.function_depth += 1;
+        self.loop_depth = 0;
+        let body = self.block();
+        self.function_depth -= 1;
+        self.loop_depth = enclosing_loop_depth;
+        let body = body?;
+
+        Ok(Rc::new(FunctionDecl { name, params, body, doc }))
+    }
+
+    fn var_declaration(&mut self, doc: Option<String>) -> ParseResult<Stmt> {
+        let name = self.consume_identifier("Expect variable name.")?;
+        let initializer = if self.matches(&[Token::Equal]) { Some(self.expression()?) } else { None };
+        self.consume(Token::Semicolon, "Expect ';' after variable declaration.")?;
+        Ok(Stmt::Var { name, initializer, doc })
+    }
+
+    fn statement(&mut self) -> ParseResult<Stmt> {
+        if self.matches(&[Token::For]) {
+            return self.for_statement();
+        }
+        if self.matches(&[Token::If]) {
+            return self.if_statement();
+        }
+        if self.matches(&[Token::Print]) {
+            return self.print_statement();
+        }
+        if self.matches(&[Token::Return]) {
+            return self.return_statement();
+        }
+        if self.matches(&[Token::While]) {
+            return self.while_statement();
+        }
+        if self.matches(&[Token::Break]) {
+            return self.break_statement();
+        }
+        if self.matches(&[Token::Continue]) {
+            return self.continue_statement();
+        }
+        if self.matches(&[Token::LeftBrace]) {
+            return Ok(Stmt::Block(self.block()?));
+        }
+        self.expression_statement()
+    }
+
+    fn return_statement(&mut self) -> ParseResult<Stmt> {
+        let keyword = self.previous();
+        if self.function_depth == 0 {
+            self.error(keyword, ErrorCode::TopLevelReturn, "Can't return from top-level code.");
+        }
+        let keyword = Identifier { lexeme: "return".to_string(), line: keyword.line(), column: keyword.column(), symbol: None };
+
+        let value = if self.check(&Token::Semicolon) { None } else { Some(self.expression()?) };
+        self.consume(Token::Semicolon, "Expect ';' after return value.")?;
+        Ok(Stmt::Return { keyword, value })
+    }
+
+    fn break_statement(&mut self) -> ParseResult<Stmt> {
+        if self.loop_depth == 0 {
+            self.error(self.previous(), ErrorCode::OutsideLoop, "Can't break outside a loop.");
+        }
+
+        self.consume(Token::Semicolon, "Expect ';' after 'break'.")?;
+        Ok(Stmt::Break)
+    }
+
+    fn continue_statement(&mut self) -> ParseResult<Stmt> {
+        if self.loop_depth == 0 {
+            self.error(self.previous(), ErrorCode::OutsideLoop, "Can't continue outside a loop.");
+        }
+
+        self.consume(Token::Semicolon, "Expect ';' after 'continue'.")?;
+        Ok(Stmt::Continue)
+    }
+
+    fn while_statement(&mut self) -> ParseResult<Stmt> {
+        self.consume(Token::LeftParen, "Expect '(' after 'while'.")?;
+        let condition = self.expression()?;
+        self.consume(Token::RightParen, "Expect ')' after condition.")?;
+        let body = Box::new(self.loop_body()?);
+
+        Ok(Stmt::While { condition, body, increment: None })
+    }
+
+    fn block(&mut self) -> ParseResult<Vec<Stmt>> {
+        let mut statements = vec![];
+        while !self.check(&Token::RightBrace) && !self.is_at_end() {
+            if let Some(statement) = self.declaration() {
+                statements.push(statement);
+            }
+        }
+
+        self.consume(Token::RightBrace, "Expect '}' after block.")?;
+        Ok(statements)
+    }
+
+    /// Desugars `for (initializer; condition; increment) body` into a `while` loop
+    fn for_statement(&mut self) -> ParseResult<Stmt> {
+        self.consume(Token::LeftParen, "Expect '(' after 'for'.")?;
+
+        let initializer = if self.matches(&[Token::Semicolon]) {
+            None
+        } else if self.matches(&[Token::Var]) {
+            Some(self.var_declaration(None)?)
+        } else {
+            Some(self.expression_statement()?)
+        };
+
+        let condition = if self.check(&Token::Semicolon) { None } else { Some(self.expression()?) };
+        self.consume(Token::Semicolon, "Expect ';' after loop condition.")?;
+
+        let increment = if self.check(&Token::RightParen) { None } else { Some(self.expression()?) };
+        self.consume(Token::RightParen, "Expect ')' after for clauses.")?;
+
+        let body = self.loop_body()?;
+        let condition = condition.unwrap_or(Expr::Literal(Value::Bool(true)));
+        let mut body = Stmt::While { condition, body: Box::new(body), increment };
+
+        if let Some(initializer) = initializer {
+            body = Stmt::Block(vec![initializer, body]);
+        }
+
+        Ok(body)
+    }
+
+    fn loop_body(&mut self) -> ParseResult<Stmt> {
+        self.loop_depth += 1;
+        let body = self.statement();
+        self.loop_depth -= 1;
+        body
+    }
+
+    fn if_statement(&mut self) -> ParseResult<Stmt> {
+        self.consume(Token::LeftParen, "Expect '(' after 'if'.")?;
+        let condition = self.expression()?;
+        self.consume(Token::RightParen, "Expect ')' after if condition.")?;
+
+        let then_branch = Box::new(self.statement()?);
+        // Greedy matching binds a dangling `else` to the nearest `if`
+        let else_branch = if self.matches(&[Token::Else]) { Some(Box::new(self.statement()?)) } else { None };
+
+        Ok(Stmt::If { condition, then_branch, else_branch })
+    }
+
+    fn print_statement(&mut self) -> ParseResult<Stmt> {
+        let value = self.expression()?;
+        self.consume(Token::Semicolon, "Expect ';' after value.")?;
+        Ok(Stmt::Print(value))
+    }
+
+    fn expression_statement(&mut self) -> ParseResult<Stmt> {
+        let expr = self.expression()?;
+        if self.repl && self.is_at_end() {
+            return Ok(Stmt::Print(expr));
+        }
+        self.consume(Token::Semicolon, "Expect ';' after expression.")?;
+        Ok(Stmt::Expression(expr))
+    }
+
+    fn expression(&mut self) -> ParseResult<Expr> {
+        self.assignment()
+    }
+
+    fn assignment(&mut self) -> ParseResult<Expr> {
+        let expr = self.or()?;
+
+        if self.matches(&[Token::Equal]) {
+            let equals = self.previous();
+            let value = self.assignment()?;
+
+            match expr {
+                Expr::Variable { name, .. } => {
+                    return Ok(Expr::Assign { name, value: Box::new(value), id: ExprId::unique() });
+                }
+                Expr::Get { object, name } => {
+                    return Ok(Expr::Set { object, name, value: Box::new(value) });
+                }
+                _ => {}
+            }
+
+            // Reported without unwinding, the parser is not confused by an invalid target
+            self.error(equals, ErrorCode::InvalidAssignmentTarget, "Invalid assignment target.");
+        }
+
+        Ok(expr)
+    }
+
+    fn or(&mut self) -> ParseResult<Expr> {
+        self.logical(&[Token::Or], LogicalOperator::Or, Self::and)
+    }
+
+    fn and(&mut self) -> ParseResult<Expr> {
+        self.logical(&[Token::And], LogicalOperator::And, Self::equality)
+    }
+
+    fn logical(&mut self, tokens: &[Token], operator: LogicalOperator, operand: fn(&mut Self) -> ParseResult<Expr>) -> ParseResult<Expr> {
+        let mut expr = operand(self)?;
+
+        while self.matches(tokens) {
+            let right = operand(self)?;
+            expr = Expr::Logical { left: Box::new(expr), operator, right: Box::new(right) };
+        }
+
+        Ok(expr)
+    }
+
+    fn equality(&mut self) -> ParseResult<Expr> {
+        self.binary(&[Token::BangEqual, Token::EqualEqual], Self::comparison)
+    }
+
+    fn comparison(&mut self) -> ParseResult<Expr> {
+        self.binary(&[Token::Greater, Token::GreaterEqual, Token::Less, Token::LessEqual], Self::term)
+    }
+
+    fn term(&mut self) -> ParseResult<Expr> {
+        self.binary(&[Token::Minus, Token::Plus], Self::factor)
+    }
+
+    fn factor(&mut self) -> ParseResult<Expr> {
+        self.binary(&[Token::Slash, Token::Star, Token::Percent], Self::unary)
+    }
+
+    /// Left-associative chain of `operand (operator operand)*`
+    fn binary(&mut self, operators: &[Token], operand: fn(&mut Self) -> ParseResult<Expr>) -> ParseResult<Expr> {
+        let mut expr = operand(self)?;
+
+        while self.matches(operators) {
+            let operator = self.previous();
+            let line = operator.line();
+            let operator = binary_operator(operator.token());
+            let right = operand(self)?;
+            expr = Expr::Binary { left: Box::new(expr), operator, right: Box::new(right), line };
+        }
+
+        Ok(expr)
+    }
+
+    fn unary(&mut self) -> ParseResult<Expr> {
+        if self.matches(&[Token::Bang, Token::Minus]) {
+            let operator = self.previous();
+            let line = operator.line();
+            let operator = if *operator.token() == Token::Bang { UnaryOperator::Not } else { UnaryOperator::Negate };
+            let right = self.unary()?;
+            return Ok(Expr::Unary { operator, right: Box::new(right), line });
+        }
+
+        self.call()
+    }
+
+    fn call(&mut self) -> ParseResult<Expr> {
+        let mut expr = self.primary()?;
+
+        loop {
+            if self.matches(&[Token::LeftParen]) {
+                expr = self.finish_call(expr)?;
+            } else if self.matches(&[Token::Dot]) {
+                let name = self.consume_identifier("Expect property name after '.'.")?;
+                expr = Expr::Get { object: Box::new(expr), name };
+            } else {
+                break;
+            }
+        }
+
+        Ok(expr)
+    }
+
+    fn finish_call(&mut self, callee: Expr) -> ParseResult<Expr> {
+        let mut arguments = vec![];
+        if !self.check(&Token::RightParen) {
+            loop {
+                if arguments.len() >= MAX_ARGUMENTS {
+                    self.error(self.peek(), ErrorCode::TooManyArguments, &format!("Can't have more than {} arguments.", MAX_ARGUMENTS));
+                }
+                arguments.push(self.expression()?);
+
+                if !self.matches(&[Token::Comma]) {
+                    break;
+                }
+            }
+        }
+
+        let paren = self.consume(Token::RightParen, "Expect ')' after arguments.")?;
+        Ok(Expr::Call { callee: Box::new(callee), arguments, line: paren.line() })
+    }
+
+    fn primary(&mut self) -> ParseResult<Expr> {
+        if let Some(value) = Option::<Value>::from(self.peek().token()) {
+            self.advance();
+            return Ok(Expr::Literal(value));
+        }
+
+        if let Token::Interpolation(_) = self.peek().token() {
+            return self.interpolation();
+        }
+
+        if self.matches(&[Token::Super]) {
+            let keyword = self.previous();
+            let keyword = Identifier { lexeme: "super".to_string(), line: keyword.line(), column: keyword.column(), symbol: None };
+            self.consume(Token::Dot, "Expect '.' after 'super'.")?;
+            let method = self.consume_identifier("Expect superclass method name.")?;
+            return Ok(Expr::Super { keyword, method, id: ExprId::unique() });
+        }
+
+        if self.matches(&[Token::Fun]) {
+            let keyword = self.previous();
+            let name = Identifier { lexeme: "anonymous".to_string(), line: keyword.line(), column: keyword.column(), symbol: None };
+            self.consume(Token::LeftParen, "Expect '(' after 'fun'.")?;
+            return Ok(Expr::Lambda(self.function_rest("function", name, None)?));
+        }
+
+        if self.matches(&[Token::This]) {
+            let keyword = self.previous();
+            let keyword = Identifier { lexeme: "this".to_string(), line: keyword.line(), column: keyword.column(), symbol: None };
+            return Ok(Expr::This { keyword, id: ExprId::unique() });
+        }
+
+        if let Token::Identifier(_) = self.peek().token() {
+            let name = identifier(self.advance());
+            return Ok(Expr::Variable { name, id: ExprId::unique() });
+        }
+
+        if self.matches(&[Token::LeftParen]) {
+            let expr = self.expression()?;
+            self.consume(Token::RightParen, "Expect ')' after expression.")?;
+            return Ok(Expr::Grouping(Box::new(expr)));
+        }
+
+        Err(self.error(self.peek(), ErrorCode::ExpectedExpression, "Expect expression."))
+    }
+
+    /// String parts alternating with embedded expressions closed by `}`, up to the `StringValue` ending the string
+    fn interpolation(&mut self) -> ParseResult<Expr> {
+        let mut parts = vec![];
+        loop {
+            let (text, more) = match self.advance().token() {
+                Token::Interpolation(text) => (text, true),
+                Token::StringValue(text) => (text, false),
+                // The rest of the string didn't scan
+                _ => return Err(self.error(self.previous(), ErrorCode::ExpectedToken, "Expect the rest of the string after '}'.")),
+            };
+            if !text.is_empty() {
+                parts.push(Expr::Literal(Value::Str(text.to_string())));
+            }
+            if !more {
+                return Ok(Expr::Interpolation(parts));
+            }
+            parts.push(self.expression()?);
+            self.consume(Token::RightBrace, "Expect '}' after interpolated expression.")?;
+        }
+    }
+
+    fn matches(&mut self, tokens: &[Token]) -> bool {
+        if tokens.iter().any(|token| self.check(token)) {
+            self.advance();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn consume(&mut self, token: Token, message: &str) -> ParseResult<&'t TokenInfo<'s>> {
+        if self.check(&token) {
+            Ok(self.advance())
+        } else {
+            Err(self.error(self.peek(), ErrorCode::ExpectedToken, message))
+        }
+    }
+
+    fn consume_identifier(&mut self, message: &str) -> ParseResult<Identifier> {
+        if let Token::Identifier(_) = self.peek().token() {
+            Ok(identifier(self.advance()))
+        } else {
+            Err(self.error(self.peek(), ErrorCode::ExpectedToken, message))
+        }
+    }
+
+    fn check(&self, token: &Token) -> bool {
+        !self.is_at_end() && self.peek().token() == token
+    }
+
+    fn advance(&mut self) -> &'t TokenInfo<'s> {
+        if !self.is_at_end() {
+            self.current += 1;
+        }
+        self.previous()
+    }
+
+    fn is_at_end(&self) -> bool {
+        *self.peek().token() == Token::EOF
+    }
+
+    fn peek(&self) -> &'t TokenInfo<'s> {
+        &self.tokens[self.current]
+    }
+
+    fn peek_next(&self) -> &'t TokenInfo<'s> {
+        &self.tokens[(self.current + 1).min(self.tokens.len() - 1)]
+    }
+
+    fn previous(&self) -> &'t TokenInfo<'s> {
+        &self.tokens[self.current - 1]
+    }
+
+    fn error(&mut self, token: &TokenInfo, code: ErrorCode, message: &str) -> ParseError {
+        self.had_errors = true;
+        let position = if *token.token() == Token::EOF {
+            " at end".to_string()
+        } else {
+            format!(" at '{}'", token.token())
+        };
+        self.error_handler.emit(&Diagnostic {
+            severity: Severity::Error,
+            code,
+            line: token.line(),
+            column: token.column(),
+            length: token.span().len(),
+            position,
+            message: message.to_string(),
+        });
+        ParseError
+    }
+
+    /// Skips tokens until the probable start of the next statement
+    fn synchronize(&mut self) {
+        self.advance();
+
+        while !self.is_at_end() {
+            if *self.previous().token() == Token::Semicolon {
+                return;
+            }
+
+            match self.peek().token() {
+                Token::Class | Token::Fun | Token::Var | Token::For | Token::If | Token::While | Token::Print
+                | Token::Return | Token::Break | Token::Continue => return,
+                _ => {}
+            }
+
+            self.advance();
+        }
+    }
+}
+
+fn identifier(token: &TokenInfo) -> Identifier {
+    match token.token() {
+        Token::Identifier(name) => {
+            Identifier { lexeme: name.to_string(), line: token.line(), column: token.column(), symbol: token.symbol() }
+        }
+        _ => unreachable!("{:?} is not an identifier", token),
+    }
+}
+
+fn binary_operator(token: &Token) -> BinaryOperator {
+    match token {
+        Token::EqualEqual => BinaryOperator::Equal,
+        Token::BangEqual => BinaryOperator::NotEqual,
+        Token::Greater => BinaryOperator::Greater,
+        Token::GreaterEqual => BinaryOperator::GreaterEqual,
+        Token::Less => BinaryOperator::Less,
+        Token::LessEqual => BinaryOperator::LessEqual,
+        Token::Plus => BinaryOperator::Add,
+        Token::Minus => BinaryOperator::Subtract,
+        Token::Star => BinaryOperator::Multiply,
+        Token::Slash => BinaryOperator::Divide,
+        Token::Percent => BinaryOperator::Remainder,
+        _ => unreachable!("{:?} is not a binary operator", token),
+    }
+}
@@ -0,0 +1,341 @@
+use super::ast::{Expr, ExprId, FunctionDecl, Identifier, Stmt};
+use super::error_code::{ErrorCode, Warnings};
+use super::error_handler::{Diagnostic, ErrorHandler, Severity};
+use super::interner::{StringInterner, Symbol};
+use super::interpreter::Interpreter;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum FunctionType {
+    None,
+    Function,
+    Method,
+    Initializer,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ClassType {
+    None,
+    Class,
+    Subclass,
+}
+
+#[derive(Debug)]
+struct Local {
+    // Whether its initializer was resolved
+    defined: bool,
+    used: bool,
+    // Set for `var` declarations, which get a warning when never used
+    declaration: Option<Identifier>,
+}
+
+impl Local {
+    fn new(defined: bool) -> Local {
+        Local { defined, used: false, declaration: None }
+    }
+}
+
+/// Computes for every local variable reference how many scopes away its declaration is,
+/// globals are left unresolved and looked up dynamically
+#[derive(Debug)]
+pub struct Resolver<'i> {
+    interpreter: &'i mut Interpreter,
+    error_handler: &'i dyn ErrorHandler,
+    // Innermost scope last
+    scopes: Vec<HashMap<Symbol, Local>>,
+    // Names of the scopes, the symbols of identifiers are only taken when it is the scanner's interner
+    interner: Rc<RefCell<StringInterner>>,
+    shares_interner: bool,
+    current_function: FunctionType,
+    current_class: ClassType,
+    warnings: Warnings,
+    had_errors: bool,
+}
+
+impl<'i> Resolver<'i> {
+    pub fn new(interpreter: &'i mut Interpreter, error_handler: &'i dyn ErrorHandler) -> Resolver<'i> {
+        Resolver {
+            interpreter,
+            error_handler,
+            scopes: vec![],
+            interner: Rc::new(RefCell::new(StringInterner::new())),
+            shares_interner: false,
+            current_function: FunctionType::None,
+            current_class: ClassType::None,
+            warnings: Warnings::default(),
+            had_errors: false,
+        }
+    }
+
+    /// Same as `new`, but reports the given warnings instead of the default ones
+    pub fn with_warnings(
+        interpreter: &'i mut Interpreter,
+        error_handler: &'i dyn ErrorHandler,
+        warnings: Warnings,
+    ) -> Resolver<'i> {
+        Resolver { warnings, ..Resolver::new(interpreter, error_handler) }
+    }
+
+    /// Keys the scopes on the symbols of identifiers scanned by `Scanner::with_interner(.., interner)`,
+    /// instead of interning every name again
+    pub fn sharing_interner(mut self, interner: Rc<RefCell<StringInterner>>) -> Resolver<'i> {
+        self.interner = interner;
+        self.shares_interner = true;
+        self
+    }
+
+    pub fn had_errors(&self) -> bool {
+        self.had_errors
+    }
+
+    pub fn resolve(&mut self, statements: &[Stmt]) {
+        for statement in statements {
+            self.resolve_statement(statement);
+        }
+    }
+
+    fn resolve_statement(&mut self, statement: &Stmt) {
+        match statement {
+            Stmt::Expression(expr) | Stmt::Print(expr) => self.resolve_expression(expr),
+            Stmt::Var { name, initializer, .. } => {
+                self.declare(name);
+                let symbol = self.symbol(name);
+                if let Some(local) = self.scopes.last_mut().and_then(|scope| scope.get_mut(&symbol)) {
+                    local.declaration = Some(name.clone());
+                }
+                if let Some(initializer) = initializer {
+                    self.resolve_expression(initializer);
+                }
+                self.define(name);
+            }
+            Stmt::Block(statements) => {
+                self.begin_scope();
+                self.resolve(statements);
+                self.end_scope();
+            }
+            Stmt::If { condition, then_branch, else_branch } => {
+                self.resolve_expression(condition);
+                self.resolve_statement(then_branch);
+                if let Some(else_branch) = else_branch {
+                    self.resolve_statement(else_branch);
+                }
+            }
+            Stmt::While { condition, body, increment } => {
+                self.resolve_expression(condition);
+                self.resolve_statement(body);
+                if let Some(increment) = increment {
+                    self.resolve_expression(increment);
+                }
+            }
+            Stmt::Function(declaration) => {
+                // Defined eagerly so the function can refer to itself recursively
+                self.declare(&declaration.name);
+                self.define(&declaration.name);
+                self.resolve_function(declaration, FunctionType::Function);
+            }
+            Stmt::Class { name, superclass, methods, .. } => {
+                let enclosing_class = self.current_class;
+                self.current_class = ClassType::Class;
+                self.declare(name);
+                self.define(name);
+
+                if let Some(superclass) = superclass {
+                    if let Expr::Variable { name: superclass_name, .. } = superclass {
+                        if superclass_name.lexeme == name.lexeme {
+                            self.error(superclass_name, ErrorCode::InheritsFromItself, "A class can't inherit from itself.");
+                        }
+                    }
+                    self.current_class = ClassType::Subclass;
+                    self.resolve_expression(superclass);
+
+                    // Methods of a subclass are bound in a scope holding `super`
+                    self.begin_scope();
+                    let symbol = self.interner.borrow_mut().intern("super");
+                    self.scopes.last_mut().unwrap().insert(symbol, Local::new(true));
+                }
+
+                // Methods are bound in a scope holding `this`
+                self.begin_scope();
+                let symbol = self.interner.borrow_mut().intern("this");
+                self.scopes.last_mut().unwrap().insert(symbol, Local::new(true));
+                for method in methods {
+                    let function_type = if method.name.lexeme == "init" { FunctionType::Initializer } else { FunctionType::Method };
+                    self.resolve_function(method, function_type);
+                }
+                self.end_scope();
+
+                if superclass.is_some() {
+                    self.end_scope();
+                }
+                self.current_class = enclosing_class;
+            }
+            Stmt::Return { keyword, value } => {
+                if let Some(value) = value {
+                    if self.current_function == FunctionType::Initializer {
+                        self.error(keyword, ErrorCode::InitializerReturnsValue, "Can't return a value from an initializer.");
+                    }
+                    self.resolve_expression(value);
+                }
+            }
+            Stmt::Break | Stmt::Continue => {}
+        }
+    }
+
+    fn resolve_expression(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Binary { left, right, .. } | Expr::Logical { left, right, .. } => {
+                self.resolve_expression(left);
+                self.resolve_expression(right);
+            }
+            Expr::Unary { right, .. } => self.resolve_expression(right),
+            Expr::Literal(_) => {}
+            Expr::Grouping(expr) => self.resolve_expression(expr),
+            Expr::Variable { name, id } => {
+                let symbol = self.symbol(name);
+                if self.scopes.last().and_then(|scope| scope.get(&symbol)).is_some_and(|local| !local.defined) {
+                    self.error(name, ErrorCode::ReadInOwnInitializer, "Can't read local variable in its own initializer.");
+                }
+                self.resolve_local(name, *id);
+            }
+            Expr::Assign { name, value, id } => {
+                self.resolve_expression(value);
+                self.resolve_local(name, *id);
+            }
+            Expr::Call { callee, arguments, .. } => {
+                self.resolve_expression(callee);
+                for argument in arguments {
+                    self.resolve_expression(argument);
+                }
+            }
+            Expr::Get { object, .. } => self.resolve_expression(object),
+            Expr::This { keyword, id } => {
+                if self.current_class == ClassType::None {
+                    self.error(keyword, ErrorCode::ThisOutsideClass, "Can't use 'this' outside of a class.");
+                    return;
+                }
+                self.resolve_local(keyword, *id);
+            }
+            Expr::Super { keyword, id, .. } => {
+                match self.current_class {
+                    ClassType::None => self.error(keyword, ErrorCode::InvalidSuper, "Can't use 'super' outside of a class."),
+                    ClassType::Class => self.error(keyword, ErrorCode::InvalidSuper, "Can't use 'super' in a class with no superclass."),
+                    ClassType::Subclass => {}
+                }
+                self.resolve_local(keyword, *id);
+            }
+            Expr::Set { object, value, .. } => {
+                self.resolve_expression(value);
+                self.resolve_expression(object);
+            }
+            Expr::Lambda(declaration) => self.resolve_function(declaration, FunctionType::Function),
+            Expr::Interpolation(parts) => {
+                for part in parts {
+                    self.resolve_expression(part);
+                }
+            }
+        }
+    }
+
+    fn resolve_function(&mut self, declaration: &FunctionDecl, function_type: FunctionType) {
+        let enclosing_function = self.current_function;
+        self.current_function = function_type;
+
+        self.begin_scope();
+        for param in &declaration.params {
+            self.declare(param);
+            self.define(param);
+        }
+        self.resolve(&declaration.body);
+        self.end_scope();
+
+        self.current_function = enclosing_function;
+    }
+
+    fn resolve_local(&mut self, name: &Identifier, id: ExprId) {
+        let symbol = self.symbol(name);
+        let found = self.scopes.iter_mut().rev().enumerate()
+            .find_map(|(depth, scope)| scope.get_mut(&symbol).map(|local| (depth, local)));
+        if let Some((depth, local)) = found {
+            local.used = true;
+            self.interpreter.resolve(id, depth);
+        }
+    }
+
+    fn begin_scope(&mut self) {
+        self.scopes.push(HashMap::new());
+    }
+
+    fn end_scope(&mut self) {
+        let scope = self.scopes.pop().unwrap_or_default();
+        let mut unused: Vec<Identifier> = scope.into_values()
+            .filter(|local| !local.used)
+            .filter_map(|local| local.declaration)
+            .collect();
+        unused.sort_by_key(|name| (name.line, name.column));
+        for name in unused {
+            self.warning(&name, ErrorCode::UnusedVariable, &format!("Local variable '{}' is never used.", name.lexeme));
+        }
+    }
+
+    fn declare(&mut self, name: &Identifier) {
+        let symbol = self.symbol(name);
+        let already_declared = match self.scopes.last_mut() {
+            Some(scope) => scope.insert(symbol, Local::new(false)).is_some(),
+            None => false,
+        };
+        if already_declared {
+            self.error(name, ErrorCode::AlreadyDeclared, "Already a variable with this name in this scope.");
+        } else if self.scopes.iter().rev().skip(1).any(|scope| scope.contains_key(&symbol)) {
+            let message = format!("Variable '{}' shadows a variable of an enclosing scope.", name.lexeme);
+            self.warning(name, ErrorCode::Shadowing, &message);
+        }
+    }
+
+    fn define(&mut self, name: &Identifier) {
+        let symbol = self.symbol(name);
+        if let Some(local) = self.scopes.last_mut().and_then(|scope| scope.get_mut(&symbol)) {
+            local.defined = true;
+        }
+    }
+
+    /// Key of `name` in the scopes. Names without a symbol of the shared interner, such as `this`, are interned here
+    fn symbol(&self, name: &Identifier) -> Symbol {
+        match name.symbol {
+            Some(symbol) if self.shares_interner => symbol,
+            _ => self.interner.borrow_mut().intern(&name.lexeme),
+        }
+    }
+
+    /// Reported as `warnings` says, which may make it an error
+    fn warning(&mut self, name: &Identifier, code: ErrorCode, message: &str) {
+        let severity = match self.warnings.severity(code) {
+            Some(severity) => severity,
+            None => return,
+        };
+        self.had_errors |= severity == Severity::Error;
+        self.error_handler.emit(&Diagnostic {
+            severity,
+            code,
+            line: name.line,
+            column: name.column,
+            length: name.lexeme.len(),
+            position: String::new(),
+            message: message.to_string(),
+        });
+    }
+
+    fn error(&mut self, name: &Identifier, code: ErrorCode, message: &str) {
+        self.had_errors = true;
+        self.error_handler.emit(&Diagnostic {
+            severity: Severity::Error,
+            code,
+            line: name.line,
+            column: name.column,
+            length: name.lexeme.len(),
+            position: format!(" at '{}'", name.lexeme),
+            message: message.to_string(),
+        });
+    }
+}
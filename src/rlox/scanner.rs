@@ -1,6 +1,18 @@
-use super::error_handler::ErrorHandler;
-use std::collections::HashMap;
+use super::error_code::ErrorCode;
+use super::error_handler::{Diagnostic, ErrorHandler, Severity};
+use super::interner::{StringInterner, Symbol};
+use super::unicode_xid::{is_xid_continue, is_xid_start};
+use super::value::format_number;
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::io::{self, BufRead};
+use std::mem;
+use std::rc::Rc;
+use std::sync::OnceLock;
 
+#[allow(clippy::upper_case_acronyms)]
 #[derive(Debug, PartialEq, Clone)]
 pub enum Token<'a> {
     // Single-character tokens
@@ -15,6 +27,13 @@ pub enum Token<'a> {
     Semicolon,
     Slash,
     Star,
+    Percent,
+    Ampersand,
+    Pipe,
+    Caret,
+    Tilde,
+    Question,
+    Colon,
 
     // One or two character tokens
     Bang,
@@ -25,15 +44,22 @@ pub enum Token<'a> {
     GreaterEqual,
     Less,
     LessEqual,
+    LeftShift,
+    RightShift,
 
     // Literals
-    Identifier(&'a str),
-    StringValue(&'a str),
+    // Borrowed from the source when scanned, owned after `into_owned`
+    Identifier(Cow<'a, str>),
+    StringValue(Cow<'a, str>),
+    // Text of a string up to an embedded `${`, the string goes on after the matching `}` and ends with `StringValue`
+    Interpolation(Cow<'a, str>),
     NumberValue(f64),
 
     // Keywords
     And,
+    Break,
     Class,
+    Continue,
     Else,
     False,
     Fun,
@@ -49,10 +75,149 @@ pub enum Token<'a> {
     Var,
     While,
 
+    // Only produced by `Scanner::keep_trivia`, the parser doesn't accept them
+    // Whole comment including its `//` or `/* */`
+    Comment(Cow<'a, str>),
+    // A run of spaces, tabs and newlines
+    Whitespace,
+
     EOF,
 }
 
-fn reserved_words() -> HashMap<&'static str, Token<'static>> {
+impl<'a> Token<'a> {
+    /// Copies borrowed text, so the token no longer refers to the source
+    pub fn into_owned(self) -> Token<'static> {
+        match self {
+            Token::Identifier(name) => Token::Identifier(Cow::Owned(name.into_owned())),
+            Token::StringValue(value) => Token::StringValue(Cow::Owned(value.into_owned())),
+            Token::Interpolation(value) => Token::Interpolation(Cow::Owned(value.into_owned())),
+            Token::NumberValue(value) => Token::NumberValue(value),
+            Token::LeftParen => Token::LeftParen,
+            Token::RightParen => Token::RightParen,
+            Token::LeftBrace => Token::LeftBrace,
+            Token::RightBrace => Token::RightBrace,
+            Token::Comma => Token::Comma,
+            Token::Dot => Token::Dot,
+            Token::Minus => Token::Minus,
+            Token::Plus => Token::Plus,
+            Token::Semicolon => Token::Semicolon,
+            Token::Slash => Token::Slash,
+            Token::Star => Token::Star,
+            Token::Percent => Token::Percent,
+            Token::Ampersand => Token::Ampersand,
+            Token::Pipe => Token::Pipe,
+            Token::Caret => Token::Caret,
+            Token::Tilde => Token::Tilde,
+            Token::Question => Token::Question,
+            Token::Colon => Token::Colon,
+            Token::Bang => Token::Bang,
+            Token::BangEqual => Token::BangEqual,
+            Token::Equal => Token::Equal,
+            Token::EqualEqual => Token::EqualEqual,
+            Token::Greater => Token::Greater,
+            Token::GreaterEqual => Token::GreaterEqual,
+            Token::Less => Token::Less,
+            Token::LessEqual => Token::LessEqual,
+            Token::LeftShift => Token::LeftShift,
+            Token::RightShift => Token::RightShift,
+            Token::And => Token::And,
+            Token::Break => Token::Break,
+            Token::Class => Token::Class,
+            Token::Continue => Token::Continue,
+            Token::Else => Token::Else,
+            Token::False => Token::False,
+            Token::Fun => Token::Fun,
+            Token::For => Token::For,
+            Token::If => Token::If,
+            Token::Nil => Token::Nil,
+            Token::Or => Token::Or,
+            Token::Print => Token::Print,
+            Token::Return => Token::Return,
+            Token::Super => Token::Super,
+            Token::This => Token::This,
+            Token::True => Token::True,
+            Token::Var => Token::Var,
+            Token::While => Token::While,
+            Token::Comment(text) => Token::Comment(Cow::Owned(text.into_owned())),
+            Token::Whitespace => Token::Whitespace,
+            Token::EOF => Token::EOF,
+        }
+    }
+}
+
+impl<'a> fmt::Display for Token<'a> {
+    /// Canonical Lox spelling of the token
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let text = match self {
+            Token::Identifier(name) => return write!(f, "{}", name),
+            Token::StringValue(value) => return write!(f, "\"{}\"", value),
+            Token::Comment(text) => return f.write_str(text),
+            Token::Interpolation(value) => return write!(f, "\"{}${{", value),
+            Token::NumberValue(value) => return f.write_str(&format_number(*value)),
+
+            Token::LeftParen => "(",
+            Token::RightParen => ")",
+            Token::LeftBrace => "{",
+            Token::RightBrace => "}",
+            Token::Comma => ",",
+            Token::Dot => ".",
+            Token::Minus => "-",
+            Token::Plus => "+",
+            Token::Semicolon => ";",
+            Token::Slash => "/",
+            Token::Star => "*",
+            Token::Percent => "%",
+            Token::Ampersand => "&",
+            Token::Pipe => "|",
+            Token::Caret => "^",
+            Token::Tilde => "~",
+            Token::Question => "?",
+            Token::Colon => ":",
+
+            Token::Bang => "!",
+            Token::BangEqual => "!=",
+            Token::Equal => "=",
+            Token::EqualEqual => "==",
+            Token::Greater => ">",
+            Token::GreaterEqual => ">=",
+            Token::Less => "<",
+            Token::LessEqual => "<=",
+            Token::LeftShift => "<<",
+            Token::RightShift => ">>",
+
+            Token::And => "and",
+            Token::Break => "break",
+            Token::Class => "class",
+            Token::Continue => "continue",
+            Token::Else => "else",
+            Token::False => "false",
+            Token::Fun => "fun",
+            Token::For => "for",
+            Token::If => "if",
+            Token::Nil => "nil",
+            Token::Or => "or",
+            Token::Print => "print",
+            Token::Return => "return",
+            Token::Super => "super",
+            Token::This => "this",
+            Token::True => "true",
+            Token::Var => "var",
+            Token::While => "while",
+
+            Token::Whitespace => "<whitespace>",
+            Token::EOF => "<eof>",
+        };
+        f.write_str(text)
+    }
+}
+
+/// Keyword table, built once and shared by all scanners
+fn reserved_words() -> &'static HashMap<&'static str, Token<'static>> {
+    static RESERVED_WORDS: OnceLock<HashMap<&'static str, Token<'static>>> = OnceLock::new();
+    RESERVED_WORDS.get_or_init(build_reserved_words)
+}
+
+fn build_reserved_words() -> HashMap<&'static str, Token<'static>> {
     let mut map = HashMap::new();
     map.insert("and", Token::And);
     map.insert("or", Token::Or);
@@ -68,186 +233,1384 @@ fn reserved_words() -> HashMap<&'static str, Token<'static>> {
     map.insert("super", Token::Super);
     map.insert("for", Token::For);
     map.insert("while", Token::While);
+    map.insert("break", Token::Break);
+    map.insert("continue", Token::Continue);
     map.insert("print", Token::Print);
     map.insert("nil", Token::Nil);
     map
 }
 
+/// Checks that every `_` in a number literal sits between two digits of the `radix`
+fn has_valid_separators(text: &str, radix: u32) -> bool {
+    let bytes = text.as_bytes();
+    let is_digit = |b: &u8| (*b as char).is_digit(radix);
+    bytes.iter().enumerate()
+        .filter(|&(_, &b)| b == b'_')
+        .all(|(i, _)| i > 0 && is_digit(&bytes[i - 1]) && bytes.get(i + 1).is_some_and(is_digit))
+}
 
-#[derive(Debug)]
+/// Drops the line break after the opening quotes, a last line holding only the indentation
+/// of the closing quotes, and the indentation common to all non-blank lines
+fn strip_indentation(text: &str) -> String {
+    let mut lines: Vec<&str> = text.split('\n').collect();
+    if lines.len() > 1 && lines[0].trim().is_empty() {
+        lines.remove(0);
+    }
+    if lines.len() > 1 && lines[lines.len() - 1].trim().is_empty() {
+        lines.pop();
+    }
+
+    let indent = lines.iter()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| line.len() - line.trim_start_matches([' ', '\t']).len())
+        .min()
+        .unwrap_or(0);
+    let lines: Vec<&str> = lines.iter()
+        .map(|line| if line.trim().is_empty() { "" } else { &line[indent..] })
+        .collect();
+    lines.join("\n")
+}
+
+/// Identifiers follow UAX #31 (`XID_Start XID_Continue*`) and may also start with `_`
+fn is_identifier_start(c: char) -> bool {
+    c == '_' || is_xid_start(c)
+}
+
+fn is_identifier_continue(c: char) -> bool {
+    is_xid_continue(c)
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct TokenInfo<'a> {
     token: Token<'a>,
     line: u32,
+    column: u32,
+    span: Span,
+    // Set for identifiers when the scanner interns names
+    symbol: Option<Symbol>,
+    // `///` comment lines right before the token
+    doc: Option<Cow<'a, str>>,
+}
+
+impl<'a> TokenInfo<'a> {
+    pub fn token(&self) -> &Token<'a> {
+        &self.token
+    }
+
+    pub fn line(&self) -> u32 {
+        self.line
+    }
+
+    /// 1-based and counted in bytes like `span`, so a char before the token in its line may count for more than one
+    pub fn column(&self) -> u32 {
+        self.column
+    }
+
+    pub fn span(&self) -> Span {
+        self.span
+    }
+
+    /// Interned name of an identifier, only available from `Scanner::with_interner`
+    pub fn symbol(&self) -> Option<Symbol> {
+        self.symbol
+    }
+
+    /// Text of the `///` comments preceding the token, without the slashes, one line per comment
+    pub fn doc(&self) -> Option<&str> {
+        self.doc.as_deref()
+    }
+
+    /// Detaches the token from the source, e.g. to keep or serialize tokens past the source lifetime
+    pub fn into_owned(self) -> TokenInfo<'static> {
+        TokenInfo {
+            token: self.token.into_owned(),
+            line: self.line,
+            column: self.column,
+            span: self.span,
+            symbol: self.symbol,
+            doc: self.doc.map(|doc| Cow::Owned(doc.into_owned())),
+        }
+    }
+
+    /// The source text of the token, `source` must be the code this token was scanned from
+    pub fn lexeme<'c>(&self, source: &'c str) -> &'c str {
+        &source[self.span.start..self.span.end]
+    }
+}
+
+/// Byte range `start..end` of a token in the source
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Span {
+        Span { start, end }
+    }
+
+    pub fn len(&self) -> usize {
+        self.end - self.start
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+}
+
+impl<'a> fmt::Display for TokenInfo<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "[line {}:{}] {}", self.line, self.column, self.token)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScanError {
+    pub code: ErrorCode,
+    pub line: u32,
+    pub column: u32,
+    // Source text the error is about
+    pub span: Span,
+    pub message: String,
+}
+
+impl ScanError {
+    pub fn diagnostic(&self) -> Diagnostic {
+        Diagnostic {
+            severity: Severity::Error,
+            code: self.code,
+            line: self.line,
+            column: self.column,
+            length: self.span.len(),
+            position: String::new(),
+            message: self.message.clone(),
+        }
+    }
+}
+
+/// Counts for the input scanned so far, `tokens` includes `EOF` once it is produced
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ScanStats {
+    pub tokens: usize,
+    pub lines: u32,
+    pub identifiers: usize,
+    pub strings: usize,
+    pub numbers: usize,
+    pub errors: usize,
+}
+
+/// `${` of a string whose embedded expression is being scanned
+#[derive(Debug, Clone, Copy)]
+struct Interpolation {
+    // Unclosed `{` inside the expression
+    depth: u32,
+    line: u32,
+    column: u32,
+    offset: usize,
+}
+
+/// Reports nothing, `scan` hands the errors collected by the scanner to the caller instead
+#[derive(Debug)]
+struct SilentErrorHandler;
+
+impl ErrorHandler for SilentErrorHandler {
+    fn emit(&self, _diagnostic: &Diagnostic) {}
+}
+
+/// Scans the whole `source`, returns either all tokens ending with `EOF` or every scan error
+pub fn scan(source: &str) -> Result<Vec<TokenInfo<'_>>, Vec<ScanError>> {
+    Scanner::new(source, &SilentErrorHandler).scan()
 }
 
 #[derive(Debug)]
 pub struct Scanner<'s> {
     // Input data
     code: &'s str,
-    chars: Vec<(usize, char)>,
     error_handler: &'s dyn ErrorHandler,
+    interner: Option<Rc<RefCell<StringInterner>>>,
 
     // Temp data
-    had_errors: bool,
+    errors: Vec<ScanError>,
     line: u32,
+    // Byte offset of the first char of the current line
+    line_start: usize,
+    // Byte offsets into `code`, always on a char boundary
     start: usize,
+    start_line: u32,
+    start_column: u32,
     current: usize,
     tokens: Vec<TokenInfo<'s>>,
-    reserved_words: HashMap<&'static str, Token<'s>>,
+    eof_emitted: bool,
+    stats: ScanStats,
+    // Interpolations being scanned, innermost last
+    interpolations: Vec<Interpolation>,
+    // The `}` closing an interpolation was just scanned, the string goes on
+    string_resumes: bool,
+    // Comments and whitespace are produced as tokens instead of skipped
+    trivia: bool,
+    // Doc comment waiting for the next token
+    doc: Option<Cow<'s, str>>,
+    // Text closing the token that may span lines the input ended inside, such as `*/`
+    unterminated: Option<String>,
 }
 
 impl<'s> Scanner<'s> {
     pub fn new<'ss>(code: &'ss str, error_handler: &'ss dyn ErrorHandler) -> Scanner<'ss> {
         Scanner {
             code,
-            chars: code.char_indices().collect(),
             error_handler,
-            had_errors: false,
+            interner: None,
+            errors: vec![],
             line: 1,
+            line_start: 0,
             start: 0,
+            start_line: 1,
+            start_column: 1,
             current: 0,
             tokens: vec![],
-            reserved_words: reserved_words(),
+            eof_emitted: false,
+            stats: ScanStats::default(),
+            interpolations: vec![],
+            string_resumes: false,
+            trivia: false,
+            doc: None,
+            unterminated: None,
         }
     }
 
-    pub fn scan_tokens(&mut self) -> &Vec<TokenInfo> {
-        while !self.is_at_end() {
-            self.start = self.current;
-            self.scan_token();
+    /// Same as `new`, but identifiers are interned into `interner`, which can be shared with later passes
+    pub fn with_interner<'ss>(
+        code: &'ss str,
+        error_handler: &'ss dyn ErrorHandler,
+        interner: Rc<RefCell<StringInterner>>,
+    ) -> Scanner<'ss> {
+        Scanner { interner: Some(interner), ..Scanner::new(code, error_handler) }
+    }
+
+    /// Produces `Comment` and `Whitespace` tokens too, so the tokens cover the whole source.
+    /// Meant for tooling such as a formatter, such tokens must be filtered out before parsing
+    pub fn keep_trivia(mut self) -> Scanner<'s> {
+        self.trivia = true;
+        self
+    }
+
+    pub fn interner(&self) -> Option<&Rc<RefCell<StringInterner>>> {
+        self.interner.as_ref()
+    }
+
+    pub fn scan_tokens(&mut self) -> &Vec<TokenInfo<'s>> {
+        while let Some(token) = self.next() {
+            self.tokens.push(token);
         }
-        self.add_token(Token::EOF);
         &self.tokens
     }
 
-    fn scan_token(&mut self) {
+    /// Scans lazily, each scan error is yielded before the token following it.
+    /// The tokens aren't kept by the scanner, errors still go to the error handler too
+    pub fn tokens(&mut self) -> Tokens<'_, 's> {
+        let reported = self.errors.len();
+        Tokens { scanner: self, reported, pending: None }
+    }
+
+    /// Same as `scan_tokens`, but also returns every error reported while scanning
+    pub fn scan_tokens_checked(&mut self) -> Result<&Vec<TokenInfo<'s>>, Vec<ScanError>> {
+        self.scan_tokens();
+        if self.had_errors() {
+            Err(self.errors.clone())
+        } else {
+            Ok(&self.tokens)
+        }
+    }
+
+    /// Scans the rest of the input, errors are also passed to the error handler as they are found
+    pub fn scan(mut self) -> Result<Vec<TokenInfo<'s>>, Vec<ScanError>> {
+        self.scan_tokens();
+        if self.had_errors() {
+            Err(self.errors)
+        } else {
+            Ok(self.tokens)
+        }
+    }
+
+    pub fn had_errors(&self) -> bool {
+        !self.errors.is_empty()
+    }
+
+    pub fn stats(&self) -> ScanStats {
+        // The line after a final newline holds no code
+        let lines = if self.code.is_empty() {
+            0
+        } else if self.is_at_end() && self.code.ends_with('\n') {
+            self.line - 1
+        } else {
+            self.line
+        };
+        ScanStats { lines, errors: self.errors.len(), ..self.stats }
+    }
+
+    fn count(&mut self, token: &TokenInfo) {
+        self.stats.tokens += 1;
+        match token.token {
+            Token::Identifier(_) => self.stats.identifiers += 1,
+            Token::StringValue(_) => self.stats.strings += 1,
+            Token::NumberValue(_) => self.stats.numbers += 1,
+            _ => {}
+        }
+    }
+
+    fn scan_token(&mut self) -> Option<TokenInfo<'s>> {
+        if mem::take(&mut self.string_resumes) {
+            return self.string(self.start).map(|t| self.token_info(t));
+        }
+
         let c = self.advance();
         let token: Option<Token> = match c {
             '(' => Some(Token::LeftParen),
             ')' => Some(Token::RightParen),
-            '{' => Some(Token::LeftBrace),
-            '}' => Some(Token::RightBrace),
+            '{' => {
+                if let Some(interpolation) = self.interpolations.last_mut() {
+                    interpolation.depth += 1;
+                }
+                Some(Token::LeftBrace)
+            }
+            '}' => {
+                match self.interpolations.last_mut() {
+                    // Closes an interpolated expression, the string continues with the next token
+                    Some(Interpolation { depth: 0, .. }) => {
+                        self.interpolations.pop();
+                        self.string_resumes = true;
+                    }
+                    Some(interpolation) => interpolation.depth -= 1,
+                    None => {}
+                }
+                Some(Token::RightBrace)
+            }
             ',' => Some(Token::Comma),
             '.' => Some(Token::Dot),
             '-' => Some(Token::Minus),
             '+' => Some(Token::Plus),
             ';' => Some(Token::Semicolon),
             '*' => Some(Token::Star),
+            '%' => Some(Token::Percent),
+            '&' => Some(Token::Ampersand),
+            '|' => Some(Token::Pipe),
+            '^' => Some(Token::Caret),
+            '~' => Some(Token::Tilde),
+            '?' => Some(Token::Question),
+            ':' => Some(Token::Colon),
 
-            '!' => Some(if self.matches('=') { Token::BangEqual } else { Token::Bang }),
-            '=' => Some(if self.matches('=') { Token::EqualEqual } else { Token::Equal }),
-            '<' => Some(if self.matches('=') { Token::LessEqual } else { Token::Less }),
-            '>' => Some(if self.matches('=') { Token::GreaterEqual } else { Token::Greater }),
+            '!' => Some(if self.matches(b'=') { Token::BangEqual } else { Token::Bang }),
+            '=' => Some(if self.matches(b'=') { Token::EqualEqual } else { Token::Equal }),
+            '<' => Some(if self.matches(b'=') {
+                Token::LessEqual
+            } else if self.matches(b'<') {
+                Token::LeftShift
+            } else {
+                Token::Less
+            }),
+            '>' => Some(if self.matches(b'=') {
+                Token::GreaterEqual
+            } else if self.matches(b'>') {
+                Token::RightShift
+            } else {
+                Token::Greater
+            }),
 
-            '/' => if self.matches('/') {
-                while self.peek() != '\n' {
+            '/' => if self.matches(b'/') {
+                // `////` and longer are plain comments
+                let doc = self.peek() == '/' && self.peek_next() != '/';
+                while !self.is_at_end() && self.peek() != '\n' {
                     self.advance();
                 }
-                None
+                if doc {
+                    self.doc_comment();
+                }
+                self.comment()
+            } else if self.matches(b'*') {
+                self.block_comment();
+                self.comment()
             } else {
                 Some(Token::Slash)
             }
 
-            ' ' | '\r' | '\t' => None,
-            '\n' => {
-                self.line += 1;
-                None
+            // `#!/usr/bin/env rlox` on the first line makes a script executable. The line is checked too,
+            // as a `StreamScanner` starts a scanner at offset 0 of every chunk of lines it scans
+            '#' if self.start == 0 && self.line == 1 && self.matches(b'!') => {
+                while !self.is_at_end() && self.peek() != '\n' {
+                    self.advance();
+                }
+                self.comment()
             }
 
-            '"' => self.string(),
+            ' ' | '\r' | '\t' | '\n' => {
+                if c == '\n' {
+                    self.new_line();
+                }
+                if !self.trivia {
+                    return None;
+                }
+                while !self.is_at_end() && matches!(self.peek(), ' ' | '\r' | '\t' | '\n') {
+                    if self.advance() == '\n' {
+                        self.new_line();
+                    }
+                }
+                Some(Token::Whitespace)
+            }
+
+            '"' => if self.code[self.current..].starts_with("\"\"") {
+                self.current += 2;
+                self.text_block()
+            } else {
+                self.string(self.current)
+            }
 
-            _ => if c.is_digit(10) {
+            _ => if c.is_ascii_digit() {
                 self.number()
-            } else if c.is_alphabetic() {
+            } else if c == 'r' && matches!(self.peek(), '"' | '#') {
+                self.raw_string()
+            } else if is_identifier_start(c) {
                 self.identifier()
             } else {
-                self.had_errors = true;
-                self.error_handler.error(self.line, &format!("Unexpected character {}", c));
+                // Escaped so that control chars such as an embedded NUL show up in the message
+                self.error(ErrorCode::UnexpectedCharacter, &format!("Unexpected character {}", c.escape_debug()));
                 None
             }
         };
 
-        match token {
-            Some(t) => self.add_token(t),
-            None => {}
-        }
+        token.map(|t| self.token_info(t))
     }
 
     fn is_at_end(&self) -> bool {
-        self.current >= self.chars.len()
+        self.current >= self.code.len()
     }
 
-    fn matches(&mut self, expected: char) -> bool {
-        if self.is_at_end() { return false; }
-        if self.chars[self.current].1 != expected { return false; }
+    /// Only for ASCII `expected`, so a byte compare is enough
+    fn matches(&mut self, expected: u8) -> bool {
+        if self.code.as_bytes().get(self.current) != Some(&expected) { return false; }
         self.current += 1;
         true
     }
 
+    /// `'\0'` past the end, which only works as a stop for loops that match specific chars.
+    /// A NUL in the source is an ordinary char, so loops that accept anything check `is_at_end`
     fn peek(&self) -> char {
-        if self.is_at_end() { return '\0'; }
-        self.chars[self.current].1
+        self.char_at(self.current)
     }
 
     fn peek_next(&self) -> char {
         if self.is_at_end() { return '\0'; }
-        if self.current + 1 >= self.chars.len() { return '\0'; }
-        self.chars[self.current + 1].1
+        self.char_at(self.current + self.peek().len_utf8())
     }
 
     fn advance(&mut self) -> char {
-        let char = self.chars[self.current].1;
-        self.current += 1;
-        char
+        let c = self.char_at(self.current);
+        self.current += c.len_utf8();
+        c
+    }
+
+    /// Char starting at byte `offset`, UTF-8 is decoded only for non-ASCII bytes
+    fn char_at(&self, offset: usize) -> char {
+        match self.code.as_bytes().get(offset) {
+            None => '\0',
+            Some(&byte) if byte.is_ascii() => byte as char,
+            Some(_) => self.code[offset..].chars().next().unwrap(),
+        }
+    }
+
+    fn begin_token(&mut self) {
+        self.start = self.current;
+        self.start_line = self.line;
+        self.start_column = self.column(self.start);
     }
 
-    fn add_token(&mut self, token_type: Token<'s>) {
-        self.tokens.push(TokenInfo { line: self.line, token: token_type })
+    fn new_line(&mut self) {
+        self.line += 1;
+        self.line_start = self.current;
+    }
+
+    fn token_info(&mut self, token: Token<'s>) -> TokenInfo<'s> {
+        // Trivia in between doesn't take the doc from the declaration it documents
+        let doc = match token {
+            Token::Comment(_) | Token::Whitespace => None,
+            _ => self.doc.take(),
+        };
+        let symbol = match (&token, &self.interner) {
+            (Token::Identifier(name), Some(interner)) => Some(interner.borrow_mut().intern(name)),
+            _ => None,
+        };
+        TokenInfo {
+            token,
+            symbol,
+            line: self.start_line,
+            column: self.start_column,
+            span: Span::new(self.start, self.current),
+            doc,
+        }
+    }
+
+    fn column(&self, offset: usize) -> u32 {
+        (offset.saturating_sub(self.line_start) + 1) as u32
     }
 
     fn current_text(&self) -> &'s str {
-        &self.code[self.chars[self.start].0..self.chars[self.current].0]
+        self.text(self.start, self.current)
     }
 
-    fn string(&mut self) -> Option<Token<'s>> {
-        while self.peek() != '"' && !self.is_at_end() {
+    fn text(&self, from: usize, to: usize) -> &'s str {
+        &self.code[from..to]
+    }
+
+    fn error(&mut self, code: ErrorCode, message: &str) {
+        // Column of the last consumed char
+        let previous = self.code[..self.current].char_indices().next_back().map_or(0, |(offset, _)| offset);
+        let column = self.column(previous);
+        let span = Span::new(previous, self.current);
+        self.push_error(ScanError { code, line: self.line, column, span, message: message.to_string() });
+    }
+
+    /// Error about the whole token scanned so far
+    fn error_at(&mut self, line: u32, column: u32, code: ErrorCode, message: &str) {
+        let span = Span::new(self.start, self.current);
+        self.push_error(ScanError { code, line, column, span, message: message.to_string() });
+    }
+
+    /// Error about the whole number scanned so far, which the message names
+    fn number_error(&mut self, message: &str) {
+        self.error_at(self.start_line, self.start_column, ErrorCode::InvalidNumber, message);
+    }
+
+    fn push_error(&mut self, error: ScanError) {
+        self.error_handler.emit(&error.diagnostic());
+        self.errors.push(error);
+    }
+
+    /// Adds the `///` line just scanned to the doc of the next token
+    fn doc_comment(&mut self) {
+        let text = self.text(self.start + 3, self.current).trim_end_matches('\r');
+        let text = text.strip_prefix(' ').unwrap_or(text);
+        self.doc = Some(match self.doc.take() {
+            Some(doc) => Cow::Owned(format!("{}\n{}", doc, text)),
+            None => Cow::Borrowed(text),
+        });
+    }
+
+    /// The comment just scanned as a token in trivia mode
+    fn comment(&self) -> Option<Token<'s>> {
+        if self.trivia {
+            Some(Token::Comment(Cow::Borrowed(self.text(self.start, self.current))))
+        } else {
+            None
+        }
+    }
+
+    fn block_comment(&mut self) {
+        let start_line = self.start_line;
+        let mut depth = 1;
+        while !self.is_at_end() {
+            match self.advance() {
+                '/' if self.matches(b'*') => depth += 1,
+                '*' if self.matches(b'/') => {
+                    depth -= 1;
+                    if depth == 0 { return; }
+                }
+                '\n' => self.new_line(),
+                _ => {}
+            }
+        }
+
+        self.unterminated = Some("*/".to_string());
+        self.error_at(start_line, self.start_column, ErrorCode::UnterminatedComment, "Unterminated block comment.");
+    }
+
+    /// String or its part after an interpolation, `content_start` is right after the opening `"` or `}`
+    fn string(&mut self, content_start: usize) -> Option<Token<'s>> {
+        // Allocated only once the first escape sequence is met, plain strings borrow from the source
+        let mut unescaped: Option<String> = None;
+        // Strings end on their line, `"""` is for multi-line text
+        while !self.is_at_end() && self.peek() != '"' && self.peek() != '\n' {
+            let c = self.advance();
+            if c == '$' && self.matches(b'{') {
+                let offset = self.current - 2;
+                self.interpolations.push(Interpolation { depth: 0, line: self.line, column: self.column(offset), offset });
+                let value = match unescaped {
+                    Some(buffer) => Cow::Owned(buffer),
+                    None => Cow::Borrowed(self.text(content_start, offset)),
+                };
+                return Some(Token::Interpolation(value));
+            } else if c == '\\' {
+                let prefix_end = self.current - 1;
+                let buffer = unescaped.get_or_insert_with(|| self.text(content_start, prefix_end).to_string());
+                if let Some(escaped) = self.escape_sequence() {
+                    buffer.push(escaped);
+                }
+            } else {
+                if let Some(buffer) = &mut unescaped {
+                    buffer.push(c);
+                }
+            }
+        }
+
+        if self.is_at_end() || self.peek() == '\n' {
+            self.error_at(self.start_line, self.start_column, ErrorCode::UnterminatedString, "Unterminated string.");
+            return None;
+        }
+
+        // The closing quote
+        self.advance();
+        let value = match unescaped {
+            Some(buffer) => Cow::Owned(buffer),
+            None => Cow::Borrowed(self.text(content_start, self.current - 1)),
+        };
+        Some(Token::StringValue(value))
+    }
+
+    /// `"""..."""` spanning lines, taken literally like a raw string.
+    /// See `strip_indentation` for how the text is laid out
+    fn text_block(&mut self) -> Option<Token<'s>> {
+        let content_start = self.current;
+        while !self.code[self.current..].starts_with("\"\"\"") {
+            if self.is_at_end() {
+                self.unterminated = Some("\"\"\"".to_string());
+                self.error_at(self.start_line, self.start_column, ErrorCode::UnterminatedString, "Unterminated text block.");
+                return None;
+            }
             if self.advance() == '\n' {
-                self.line += 1;
+                self.new_line();
             }
         }
+        let content = self.text(content_start, self.current);
+        self.current += 3;
+        Some(Token::StringValue(Cow::Owned(strip_indentation(content))))
+    }
 
-        if self.is_at_end() {
-            self.error_handler.error(self.line, "Unterminated string.");
-            None
-        } else {
-            Some(Token::StringValue(self.current_text()))
+    /// `r"..."` without escapes or interpolation. Any number of `#` around the quotes, as in `r#"say "hi""#`,
+    /// lets the string contain `"` followed by fewer `#`
+    fn raw_string(&mut self) -> Option<Token<'s>> {
+        let mut hashes = 0;
+        while self.matches(b'#') {
+            hashes += 1;
+        }
+        if !self.matches(b'"') {
+            self.error(ErrorCode::InvalidRawString, "Expect '\"' to start a raw string.");
+            return None;
+        }
+
+        let content_start = self.current;
+        loop {
+            if self.is_at_end() {
+                self.unterminated = Some(format!("\"{}", "#".repeat(hashes)));
+                self.error_at(self.start_line, self.start_column, ErrorCode::UnterminatedString, "Unterminated raw string.");
+                return None;
+            }
+            let c = self.advance();
+            if c == '\n' {
+                self.new_line();
+            } else if c == '"' && self.code.as_bytes()[self.current..].iter().take_while(|&&b| b == b'#').count() >= hashes {
+                let content_end = self.current - 1;
+                self.current += hashes;
+                return Some(Token::StringValue(Cow::Borrowed(self.text(content_start, content_end))));
+            }
+        }
+    }
+
+    fn escape_sequence(&mut self) -> Option<char> {
+        // The string itself reports being cut off
+        if self.is_at_end() || self.peek() == '\n' { return None; }
+
+        match self.advance() {
+            'n' => Some('\n'),
+            't' => Some('\t'),
+            'r' => Some('\r'),
+            '\\' => Some('\\'),
+            '"' => Some('"'),
+            '$' => Some('$'),
+            'u' => self.unicode_escape(),
+            c => {
+                self.error(ErrorCode::InvalidEscape, &format!("Unknown escape sequence '\\{}'.", c.escape_default()));
+                None
+            }
         }
     }
 
+    fn unicode_escape(&mut self) -> Option<char> {
+        if !self.matches(b'{') {
+            self.error(ErrorCode::InvalidEscape, "Expected '{' after '\\u' in unicode escape.");
+            return None;
+        }
+
+        let digits_start = self.current;
+        while self.peek().is_ascii_hexdigit() { self.advance(); }
+        let digits = self.text(digits_start, self.current);
+
+        if !self.matches(b'}') {
+            self.error(ErrorCode::InvalidEscape, "Expected '}' to close unicode escape.");
+            return None;
+        }
+        if digits.is_empty() {
+            self.error(ErrorCode::InvalidEscape, "Empty unicode escape.");
+            return None;
+        }
+
+        match u32::from_str_radix(digits, 16).ok().and_then(char::from_u32) {
+            Some(c) => Some(c),
+            None => {
+                self.error(ErrorCode::InvalidEscape, &format!("Invalid unicode code point '{}'.", digits));
+                None
+            }
+        }
+    }
+
+    /// A `.` belongs to the number only when a digit follows, so `4.5` is one number and `4.foo` is
+    /// `Number`, `Dot`, `Identifier`. A dot followed by anything else, as in `4.` or `4.;`, is an error
     fn number(&mut self) -> Option<Token<'s>> {
-        while self.peek().is_digit(10) { self.advance(); }
+        if self.code.as_bytes()[self.start] == b'0' {
+            match self.peek() {
+                'x' => return self.radix_number(16),
+                'b' => return self.radix_number(2),
+                'o' => return self.radix_number(8),
+                _ => {}
+            }
+        }
 
-        if self.peek() == '.' && self.peek_next().is_digit(10) {
+        self.decimal_digits();
+
+        // `4._5` is a misplaced separator, while `4._x` calls a method named `_x`
+        let fraction = self.peek_next().is_ascii_digit() || (self.peek_next() == '_' && self.char_at(self.current + 2).is_ascii_digit());
+        if self.peek() == '.' && fraction {
+            self.advance();
+            self.decimal_digits();
+        } else if self.peek() == '.' && !is_identifier_start(self.peek_next()) {
             self.advance();
-            while self.peek().is_digit(10) { self.advance(); }
+            self.number_error(&format!("Expected digits after '.' in number '{}'.", self.current_text()));
+            return None;
         }
 
-        match self.current_text().parse::<f64>() {
+        if self.peek() == 'e' || self.peek() == 'E' {
+            self.advance();
+            if self.peek() == '+' || self.peek() == '-' { self.advance(); }
+            if !self.peek().is_ascii_digit() {
+                self.number_error(&format!("Expected digits in exponent of number '{}'.", self.current_text()));
+                return None;
+            }
+            self.decimal_digits();
+        }
+
+        let text = self.current_text();
+        if !has_valid_separators(text, 10) {
+            self.number_error(&format!("Digit separator '_' must be placed between digits in number '{}'.", text));
+            return None;
+        }
+
+        let digits = if text.contains('_') { Cow::Owned(text.replace('_', "")) } else { Cow::Borrowed(text) };
+        match digits.parse::<f64>() {
             Ok(val) => Some(Token::NumberValue(val)),
             Err(err) => {
-                self.error_handler.error(self.line, &format!("{}", err));
+                self.number_error(&format!("{}", err));
+                None
+            }
+        }
+    }
+
+    fn decimal_digits(&mut self) {
+        while self.peek().is_ascii_digit() || self.peek() == '_' { self.advance(); }
+    }
+
+    fn radix_number(&mut self, radix: u32) -> Option<Token<'s>> {
+        // The radix prefix
+        self.advance();
+        let digits_start = self.current;
+        while self.peek().is_ascii_alphanumeric() || self.peek() == '_' { self.advance(); }
+        let digits = self.text(digits_start, self.current);
+
+        if digits.is_empty() {
+            self.number_error(&format!("Expected digits after '{}'.", self.current_text()));
+            return None;
+        }
+        if let Some(invalid) = digits.chars().find(|&c| c != '_' && !c.is_digit(radix)) {
+            self.number_error(&format!("Invalid digit '{}' in number '{}'.", invalid, self.current_text()));
+            return None;
+        }
+        if !has_valid_separators(digits, radix) {
+            let message = format!("Digit separator '_' must be placed between digits in number '{}'.", self.current_text());
+            self.number_error(&message);
+            return None;
+        }
+
+        let digits = if digits.contains('_') { Cow::Owned(digits.replace('_', "")) } else { Cow::Borrowed(digits) };
+        match u64::from_str_radix(&digits, radix) {
+            Ok(val) => Some(Token::NumberValue(val as f64)),
+            Err(err) => {
+                self.number_error(&format!("{}", err));
                 None
             }
         }
     }
 
     fn identifier(&mut self) -> Option<Token<'s>> {
-        while self.peek().is_alphanumeric() { self.advance(); }
+        while is_identifier_continue(self.peek()) { self.advance(); }
 
         let text = self.current_text();
-        match self.reserved_words.get(text) {
+        match reserved_words().get(text) {
             Some(token) => Some(token.clone()),
-            None => Some(Token::Identifier(text))
+            None => Some(Token::Identifier(Cow::Borrowed(text)))
+        }
+    }
+}
+
+/// Lazily scans one token per call, the last token is always `EOF`
+impl<'s> Iterator for Scanner<'s> {
+    type Item = TokenInfo<'s>;
+
+    fn next(&mut self) -> Option<TokenInfo<'s>> {
+        while !self.is_at_end() || self.string_resumes {
+            self.begin_token();
+            if let Some(token) = self.scan_token() {
+                self.count(&token);
+                return Some(token);
+            }
+        }
+
+        if self.eof_emitted {
+            return None;
+        }
+        self.eof_emitted = true;
+        // Kept, a `StreamScanner` waits for more input while an interpolation is open
+        for interpolation in self.interpolations.clone() {
+            let span = Span::new(interpolation.offset, interpolation.offset + 2);
+            let message = "Unterminated interpolation.".to_string();
+            let code = ErrorCode::UnterminatedInterpolation;
+            self.push_error(ScanError { code, line: interpolation.line, column: interpolation.column, span, message });
+        }
+        self.begin_token();
+        let eof = self.token_info(Token::EOF);
+        self.count(&eof);
+        Some(eof)
+    }
+}
+
+/// Iterator returned by `Scanner::tokens`
+#[derive(Debug)]
+pub struct Tokens<'a, 's> {
+    scanner: &'a mut Scanner<'s>,
+    // Errors of the scanner already yielded
+    reported: usize,
+    // Token scanned after errors that are yielded first
+    pending: Option<TokenInfo<'s>>,
+}
+
+impl<'a, 's> Iterator for Tokens<'a, 's> {
+    type Item = Result<TokenInfo<'s>, ScanError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(error) = self.scanner.errors.get(self.reported) {
+            self.reported += 1;
+            return Some(Err(error.clone()));
+        }
+        if let Some(token) = self.pending.take() {
+            return Some(Ok(token));
+        }
+
+        let token = self.scanner.next()?;
+        if self.reported < self.scanner.errors.len() {
+            self.pending = Some(token);
+            return self.next();
+        }
+        Some(Ok(token))
+    }
+}
+
+/// Line the pending lines of a `StreamScanner` wait for before they are worth scanning again
+#[derive(Debug)]
+enum Awaited {
+    // One containing the text that closes the unterminated token
+    Text(String),
+    // One with code, which takes the doc comment scanned so far
+    Code,
+}
+
+impl Awaited {
+    fn is_met_by(&self, line: &str) -> bool {
+        match self {
+            Awaited::Text(text) => line.contains(text.as_str()),
+            Awaited::Code => {
+                let line = line.trim_start();
+                !line.is_empty() && !line.starts_with("//")
+            }
+        }
+    }
+}
+
+/// Scans a program as it is read, e.g. from a pipe, producing tokens line by line.
+/// Tokens are owned as the input they come from isn't kept
+#[derive(Debug)]
+pub struct StreamScanner<'h, R> {
+    reader: R,
+    error_handler: &'h dyn ErrorHandler,
+    // Lines read but not scanned yet, as a token in them continues on the next line
+    pending: String,
+    // Position of `pending` in the whole input
+    line: u32,
+    offset: usize,
+    tokens: VecDeque<TokenInfo<'static>>,
+    errors: Vec<ScanError>,
+    finished: bool,
+    // Set while `pending` is incomplete, rescanning it for every line of a long comment would be quadratic
+    awaited: Option<Awaited>,
+}
+
+impl<'h, R: BufRead> StreamScanner<'h, R> {
+    pub fn new(reader: R, error_handler: &'h dyn ErrorHandler) -> StreamScanner<'h, R> {
+        StreamScanner {
+            reader,
+            error_handler,
+            pending: String::new(),
+            line: 1,
+            offset: 0,
+            tokens: VecDeque::new(),
+            errors: vec![],
+            finished: false,
+            awaited: None,
+        }
+    }
+
+    pub fn had_errors(&self) -> bool {
+        !self.errors.is_empty()
+    }
+
+    /// Scans the pending lines unless their last token goes on, `at_end` when no more input follows
+    fn scan_pending(&mut self, at_end: bool) {
+        let mut scanner = Scanner::new(&self.pending, &SilentErrorHandler);
+        scanner.line = self.line;
+        scanner.start_line = self.line;
+        let tokens: Vec<TokenInfo> = scanner.by_ref().collect();
+        // Each of these has to end before the lines are complete, so waiting for any of them will do.
+        // A doc comment belongs to a token on a later line, not to the `EOF` of the lines read so far
+        self.awaited = if let Some(text) = scanner.unterminated.take() {
+            Some(Awaited::Text(text))
+        } else if !scanner.interpolations.is_empty() {
+            Some(Awaited::Text("}".to_string()))
+        } else if tokens.last().is_some_and(|token| token.doc.is_some()) {
+            Some(Awaited::Code)
+        } else {
+            None
+        };
+        if self.awaited.is_some() && !at_end {
+            return;
+        }
+
+        for mut error in scanner.errors.drain(..) {
+            self.error_handler.emit(&error.diagnostic());
+            error.span = Span::new(error.span.start + self.offset, error.span.end + self.offset);
+            self.errors.push(error);
+        }
+        for token in tokens {
+            if token.token == Token::EOF && !at_end {
+                continue;
+            }
+            let mut token = token.into_owned();
+            token.span = Span::new(token.span.start + self.offset, token.span.end + self.offset);
+            self.tokens.push_back(token);
         }
+        self.line = scanner.line;
+        self.offset += self.pending.len();
+        self.pending.clear();
     }
-}
\ No newline at end of file
+}
+
+/// Reads more input whenever the tokens scanned so far run out, the last token is `EOF`
+impl<'h, R: BufRead> Iterator for StreamScanner<'h, R> {
+    type Item = io::Result<TokenInfo<'static>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(token) = self.tokens.pop_front() {
+                return Some(Ok(token));
+            }
+            if self.finished {
+                return None;
+            }
+
+            let line_start = self.pending.len();
+            match self.reader.read_line(&mut self.pending) {
+                Ok(read) => {
+                    // Only the last line has no line break
+                    self.finished = read == 0 || !self.pending.ends_with('\n');
+                    let line = &self.pending[line_start..];
+                    if self.finished || self.awaited.as_ref().is_none_or(|awaited| awaited.is_met_by(line)) {
+                        self.scan_pending(self.finished);
+                    }
+                }
+                Err(err) => {
+                    self.finished = true;
+                    return Some(Err(err));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rlox::CollectingErrorHandler;
+
+    fn tokens(source: &str) -> Vec<Token<'_>> {
+        scan(source).expect("no scan errors").into_iter().map(|info| info.token).collect()
+    }
+
+    fn error_codes(source: &str) -> Vec<ErrorCode> {
+        scan(source).expect_err("scan errors").iter().map(|error| error.code).collect()
+    }
+
+    /// Tokens and errors, for sources that don't scan cleanly
+    fn scan_with_errors(source: &str) -> (Vec<Token<'_>>, Vec<ScanError>) {
+        let mut scanner = Scanner::new(source, &SilentErrorHandler);
+        let tokens = scanner.scan_tokens().iter().map(|info| info.token.clone()).collect();
+        (tokens, scanner.errors)
+    }
+
+    #[test]
+    fn digit_separators() {
+        assert_eq!(tokens("1_000"), vec![Token::NumberValue(1000.0), Token::EOF]);
+        assert_eq!(tokens("0xFF_FF"), vec![Token::NumberValue(65535.0), Token::EOF]);
+        assert_eq!(tokens("0b1010_1010"), vec![Token::NumberValue(170.0), Token::EOF]);
+        for source in &["1__000", "1_", "1_.5", "4._5", "0x_FF", "0xFF_", "0xF__F"] {
+            assert_eq!(error_codes(source), vec![ErrorCode::InvalidNumber], "{}", source);
+        }
+        // An identifier may start with `_`
+        let expected = vec![Token::NumberValue(4.0), Token::Dot, Token::Identifier(Cow::Borrowed("_x")), Token::EOF];
+        assert_eq!(tokens("4._x"), expected);
+    }
+
+    #[test]
+    fn unicode_identifiers() {
+        // `e` followed by a combining acute accent, which only XID_Continue accepts
+        let ident = |name| Token::Identifier(Cow::Borrowed(name));
+        let expected = vec![ident("café"), ident("Δ"), ident("名前"), ident("cafe\u{301}"), Token::EOF];
+        assert_eq!(tokens("café Δ 名前 cafe\u{301}"), expected);
+    }
+
+    #[test]
+    fn line_comment_at_end_of_input() {
+        assert_eq!(tokens("// comment"), vec![Token::EOF]);
+        assert_eq!(tokens("print 1; // comment"), vec![Token::Print, Token::NumberValue(1.0), Token::Semicolon, Token::EOF]);
+    }
+
+    #[test]
+    fn percent() {
+        assert_eq!(tokens("10 % 3"), vec![Token::NumberValue(10.0), Token::Percent, Token::NumberValue(3.0), Token::EOF]);
+    }
+
+    #[test]
+    fn bitwise_operators() {
+        let expected = vec![
+            Token::Ampersand,
+            Token::Pipe,
+            Token::Caret,
+            Token::Tilde,
+            Token::LeftShift,
+            Token::RightShift,
+            Token::EOF,
+        ];
+        assert_eq!(tokens("& | ^ ~ << >>"), expected);
+        let ident = |name| Token::Identifier(Cow::Borrowed(name));
+        assert_eq!(tokens("a < b"), vec![ident("a"), Token::Less, ident("b"), Token::EOF]);
+        assert_eq!(tokens("<= >="), vec![Token::LessEqual, Token::GreaterEqual, Token::EOF]);
+        // There is no compound assignment
+        assert_eq!(tokens("<<="), vec![Token::LeftShift, Token::Equal, Token::EOF]);
+        assert_eq!(tokens(">>="), vec![Token::RightShift, Token::Equal, Token::EOF]);
+    }
+
+    #[test]
+    fn conditional_operator() {
+        let ident = |name| Token::Identifier(Cow::Borrowed(name));
+        let expected = vec![ident("a"), Token::Question, ident("b"), Token::Colon, ident("c"), Token::EOF];
+        assert_eq!(tokens("a ? b : c"), expected);
+        assert_eq!(tokens("a?b:c"), expected);
+    }
+
+    #[test]
+    fn unterminated_string_reports_its_first_line() {
+        let string = format!("print 1;\n\nvar s = \"{}", "\ntext".repeat(17));
+        let errors = scan(&string).unwrap_err();
+        assert_eq!((errors[0].code, errors[0].line, errors[0].column), (ErrorCode::UnterminatedString, 3, 9));
+
+        let errors = scan("\n\nprint \"text").unwrap_err();
+        assert_eq!((errors[0].code, errors[0].line), (ErrorCode::UnterminatedString, 3));
+    }
+
+    #[test]
+    fn owned_tokens_outlive_the_source() {
+        let tokens: Vec<TokenInfo<'static>> = {
+            let source = String::from("// Comment\nvar name = \"text\";");
+            scan(&source).unwrap().into_iter().map(TokenInfo::into_owned).collect()
+        };
+        let kinds: Vec<&Token> = tokens.iter().map(TokenInfo::token).collect();
+        assert_eq!(kinds, vec![
+            &Token::Var,
+            &Token::Identifier(Cow::Owned("name".to_string())),
+            &Token::Equal,
+            &Token::StringValue(Cow::Owned("text".to_string())),
+            &Token::Semicolon,
+            &Token::EOF,
+        ]);
+        assert_eq!((tokens[1].line(), tokens[1].column(), tokens[1].span()), (2, 5, Span::new(15, 19)));
+    }
+
+    #[test]
+    fn number_followed_by_dot() {
+        assert_eq!(tokens("4.foo"), vec![
+            Token::NumberValue(4.0),
+            Token::Dot,
+            Token::Identifier(Cow::Borrowed("foo")),
+            Token::EOF,
+        ]);
+        assert_eq!(tokens("4.5"), vec![Token::NumberValue(4.5), Token::EOF]);
+        assert_eq!(error_codes("4."), vec![ErrorCode::InvalidNumber]);
+        assert_eq!(error_codes("print 4.;"), vec![ErrorCode::InvalidNumber]);
+    }
+
+    #[test]
+    fn invalid_number_spans_the_literal() {
+        for (source, start, end) in &[("0b102", 0, 5), ("print 0x;", 6, 8), ("1.5e+", 0, 5), ("1__0", 0, 4)] {
+            let (_, errors) = scan_with_errors(source);
+            assert_eq!(errors[0].span, Span::new(*start, *end), "{}", source);
+            assert_eq!(errors[0].column as usize, start + 1, "{}", source);
+        }
+    }
+
+    #[test]
+    fn stats() {
+        let mut scanner = Scanner::new("var name = \"text\";\nprint name + 12;\n", &SilentErrorHandler);
+        scanner.scan_tokens();
+        let expected = ScanStats { tokens: 11, lines: 2, identifiers: 2, strings: 1, numbers: 1, errors: 0 };
+        assert_eq!(scanner.stats(), expected);
+
+        let mut scanner = Scanner::new("var a = @;\nvar b = #;", &SilentErrorHandler);
+        scanner.scan_tokens();
+        assert_eq!(scanner.stats(), ScanStats { tokens: 9, lines: 2, identifiers: 2, strings: 0, numbers: 0, errors: 2 });
+    }
+
+    #[test]
+    fn embedded_nul() {
+        // A NUL is an ordinary char in strings and comments, it doesn't end them as the end of input would
+        assert_eq!(tokens("\"a\0b\""), vec![Token::StringValue(Cow::Borrowed("a\0b")), Token::EOF]);
+        assert_eq!(tokens("// a\0b\n1"), vec![Token::NumberValue(1.0), Token::EOF]);
+        assert_eq!(tokens("/* a\0b */ 1"), vec![Token::NumberValue(1.0), Token::EOF]);
+
+        let errors = scan("1 \0 2").unwrap_err();
+        assert_eq!((errors[0].code, errors[0].column), (ErrorCode::UnexpectedCharacter, 3));
+        assert_eq!(errors[0].message, "Unexpected character \\0");
+    }
+
+    #[test]
+    fn interpolation() {
+        assert_eq!(tokens("\"a ${x} b ${y} c\""), vec![
+            Token::Interpolation(Cow::Borrowed("a ")),
+            Token::Identifier(Cow::Borrowed("x")),
+            Token::RightBrace,
+            Token::Interpolation(Cow::Borrowed(" b ")),
+            Token::Identifier(Cow::Borrowed("y")),
+            Token::RightBrace,
+            Token::StringValue(Cow::Borrowed(" c")),
+            Token::EOF,
+        ]);
+        // Braces inside the expression don't close it
+        assert_eq!(tokens("\"${ {} }\"")[1..4], [Token::LeftBrace, Token::RightBrace, Token::RightBrace]);
+    }
+
+    #[test]
+    fn unterminated_interpolation() {
+        let errors = scan("print \"a ${x").unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert_eq!((errors[0].code, errors[0].line, errors[0].column), (ErrorCode::UnterminatedInterpolation, 1, 10));
+        assert_eq!(errors[0].span, Span::new(9, 11));
+    }
+
+    #[test]
+    fn identifier_rules() {
+        assert_eq!(tokens("café 变量 _private snake_case"), vec![
+            Token::Identifier(Cow::Borrowed("café")),
+            Token::Identifier(Cow::Borrowed("变量")),
+            Token::Identifier(Cow::Borrowed("_private")),
+            Token::Identifier(Cow::Borrowed("snake_case")),
+            Token::EOF,
+        ]);
+
+        // `€` is no XID_Start char, the rest is an identifier of its own
+        let (tokens, errors) = scan_with_errors("€x");
+        assert_eq!(tokens, vec![Token::Identifier(Cow::Borrowed("x")), Token::EOF]);
+        assert_eq!((errors[0].code, errors[0].message.as_str()), (ErrorCode::UnexpectedCharacter, "Unexpected character €"));
+    }
+
+    #[test]
+    fn columns_after_multi_byte_chars() {
+        let source = "var 变量 = \"é\"; x";
+        let tokens = scan(source).unwrap();
+        let columns: Vec<u32> = tokens.iter().map(TokenInfo::column).collect();
+        // `变` and `量` take 3 bytes each, `é` 2
+        assert_eq!(columns, vec![1, 5, 12, 14, 18, 20, 21]);
+        for token in &tokens {
+            assert_eq!(token.column() as usize - 1, token.span().start);
+        }
+    }
+
+    #[test]
+    fn comments_at_end_of_input() {
+        assert_eq!(tokens(""), vec![Token::EOF]);
+        assert_eq!(tokens("//"), vec![Token::EOF]);
+        assert_eq!(tokens("1 // comment\n"), vec![Token::NumberValue(1.0), Token::EOF]);
+        assert_eq!(tokens("1\n/* comment */"), vec![Token::NumberValue(1.0), Token::EOF]);
+        assert_eq!(scan("1 // comment").unwrap()[1].line(), 1);
+    }
+
+    #[test]
+    fn unterminated_block_comment() {
+        let (tokens, errors) = scan_with_errors("1 /* comment");
+        assert_eq!(tokens, vec![Token::NumberValue(1.0), Token::EOF]);
+        assert_eq!((errors[0].code, errors[0].line, errors[0].column), (ErrorCode::UnterminatedComment, 1, 3));
+
+        // The inner comment is closed, the outer one isn't
+        let (tokens, errors) = scan_with_errors("/* outer\n/* inner */\n");
+        assert_eq!(tokens, vec![Token::EOF]);
+        assert_eq!(errors.len(), 1);
+        assert_eq!((errors[0].code, errors[0].line), (ErrorCode::UnterminatedComment, 1));
+    }
+
+    #[test]
+    fn stream_scans_like_a_whole_source() {
+        let sources = [
+            "#!/usr/bin/env rlox\nprint 1;\n",
+            "print 1;\n#!oops\n",
+            "var s = \"a ${1 +\n2} b\";\n/* multi\nline */ print s;",
+            "print \"unterminated\nprint @;",
+            "var t = \"\"\"\n  text\n  block\n  \"\"\";\nprint r#\"raw\n\"\"#;\n",
+            "/// Doc\n///\n\n// plain\nvar a = 1;\n",
+            "/* outer\n/* inner */\nstill */ print 1;\n",
+        ];
+        for source in &sources {
+            let file_handler = CollectingErrorHandler::new();
+            let file_tokens: Vec<TokenInfo> =
+                Scanner::new(source, &file_handler).map(TokenInfo::into_owned).collect();
+
+            let stream_handler = CollectingErrorHandler::new();
+            let stream_tokens: Vec<TokenInfo> = StreamScanner::new(source.as_bytes(), &stream_handler)
+                .collect::<io::Result<_>>()
+                .unwrap();
+
+            assert_eq!(stream_tokens, file_tokens, "{}", source);
+            assert_eq!(stream_handler.diagnostics(), file_handler.diagnostics(), "{}", source);
+        }
+    }
+
+    #[test]
+    fn shebang_only_on_the_first_line() {
+        let handler = CollectingErrorHandler::new();
+        let mut stream = StreamScanner::new("print 1;\n#!oops\n".as_bytes(), &handler);
+        stream.by_ref().for_each(drop);
+        assert!(stream.had_errors());
+        let diagnostics = handler.diagnostics();
+        assert_eq!((diagnostics[0].code, diagnostics[0].line), (ErrorCode::UnexpectedCharacter, 2));
+    }
+
+    #[test]
+    fn stream_waits_out_a_long_comment() {
+        let mut source = String::from("/*\n");
+        for _ in 0..20_000 {
+            source.push_str("line inside the comment\n");
+        }
+        source.push_str("*/ print 1;\n");
+
+        let handler = CollectingErrorHandler::new();
+        let tokens: Vec<TokenInfo> = StreamScanner::new(source.as_bytes(), &handler)
+            .collect::<io::Result<_>>()
+            .unwrap();
+        let kinds: Vec<Token> = tokens.iter().map(|info| info.token.clone()).collect();
+        assert_eq!(kinds, vec![Token::Print, Token::NumberValue(1.0), Token::Semicolon, Token::EOF]);
+        assert_eq!(tokens[0].line(), 20_002);
+        assert!(handler.diagnostics().is_empty());
+    }
+
+    #[test]
+    fn trivia_round_trips_the_source() {
+        let source = "#!/usr/bin/env rlox\n/// Doc\nvar a = 1; // trailing\n\t/* block /* nested */ */\r\nprint \"${a}\" + r\"raw\";\n";
+        let tokens = Scanner::new(source, &SilentErrorHandler).keep_trivia().scan().unwrap();
+        let text: String = tokens.iter().map(|token| token.lexeme(source)).collect();
+        assert_eq!(text, source);
+
+        let trivia: Vec<&Token> = tokens.iter()
+            .map(|token| &token.token)
+            .filter(|token| matches!(token, Token::Comment(_)))
+            .collect();
+        assert_eq!(trivia, vec![
+            &Token::Comment(Cow::Borrowed("#!/usr/bin/env rlox")),
+            &Token::Comment(Cow::Borrowed("/// Doc")),
+            &Token::Comment(Cow::Borrowed("// trailing")),
+            &Token::Comment(Cow::Borrowed("/* block /* nested */ */")),
+        ]);
+        // The doc still goes to the declaration, past the whitespace in between
+        assert_eq!(tokens.iter().find(|token| token.token == Token::Var).unwrap().doc(), Some("Doc"));
+    }
+
+    #[test]
+    fn raw_strings() {
+        let string = |text| vec![Token::StringValue(Cow::Borrowed(text)), Token::EOF];
+        assert_eq!(tokens(r#"r"C:\path\n""#), string(r"C:\path\n"));
+        assert_eq!(tokens(r#"r"${not} interpolated""#), string("${not} interpolated"));
+        // More `#` than any `"#` inside make room for quotes
+        assert_eq!(tokens(r###"r#"say "hi""#"###), string(r#"say "hi""#));
+        assert_eq!(tokens(r###"r##"a "# b"##"###), string(r##"a "# b"##));
+        assert_eq!(tokens("r\"two\nlines\""), string("two\nlines"));
+        // `r` alone is still a name
+        assert_eq!(tokens("r"), vec![Token::Identifier(Cow::Borrowed("r")), Token::EOF]);
+    }
+
+    #[test]
+    fn invalid_raw_strings() {
+        let (_, errors) = scan_with_errors("r#x");
+        assert_eq!((errors[0].code, errors[0].message.as_str()), (ErrorCode::InvalidRawString, "Expect '\"' to start a raw string."));
+        // Fewer `#` than the opening ones don't close it
+        let (_, errors) = scan_with_errors("r##\"text\"#");
+        assert_eq!((errors[0].code, errors[0].message.as_str()), (ErrorCode::UnterminatedString, "Unterminated raw string."));
+        assert_eq!(errors[0].span, Span::new(0, 10));
+        // Reported at the line it starts on
+        let (_, errors) = scan_with_errors(&format!("\n\nr\"{}", "\ntext".repeat(17)));
+        assert_eq!((errors[0].code, errors[0].line), (ErrorCode::UnterminatedString, 3));
+    }
+
+    #[test]
+    fn text_block_indentation() {
+        // The line breaks after the opening and before the closing quotes go, and so does the common indentation
+        assert_eq!(strip_indentation("\n    a\n      b\n    "), "a\n  b");
+        // Blank lines neither count for the indentation nor keep theirs
+        assert_eq!(strip_indentation("\n    a\n\n  \n    b\n"), "a\n\n\nb");
+        // Text on the lines of the quotes is kept
+        assert_eq!(strip_indentation("  a\n  b"), "a\nb");
+        assert_eq!(strip_indentation("one line"), "one line");
+        assert_eq!(strip_indentation(""), "");
+        assert_eq!(strip_indentation("\n"), "");
+        // A tab counts as one char of indentation like a space
+        assert_eq!(strip_indentation("\n\ta\n\t\tb\n"), "a\n\tb");
+        assert_eq!(tokens("\"\"\"\n  x\n  \"\"\""), vec![Token::StringValue(Cow::Owned("x".to_string())), Token::EOF]);
+    }
+
+    #[test]
+    fn unterminated_text_block() {
+        let source = format!("print 1;\n\nvar s = \"\"\"{}", "\ntext".repeat(17));
+        let (_, errors) = scan_with_errors(&source);
+        assert_eq!((errors[0].code, errors[0].line, errors[0].column), (ErrorCode::UnterminatedString, 3, 9));
+        // A lone `"` inside doesn't end it
+        let (_, errors) = scan_with_errors("\"\"\"\na \" b\n");
+        assert_eq!((errors[0].code, errors[0].line), (ErrorCode::UnterminatedString, 1));
+    }
+
+    #[test]
+    fn doc_comments() {
+        let tokens = scan("/// Adds.\n///   Indented\nfun f() {}\n//// Not a doc\nvar a;").unwrap();
+        assert_eq!(tokens[0].doc(), Some("Adds.\n  Indented"));
+        assert!(tokens[1..].iter().all(|token| token.doc().is_none()));
+        // Owned tokens keep their doc
+        assert_eq!(tokens[0].clone().into_owned().doc(), Some("Adds.\n  Indented"));
+    }
+}
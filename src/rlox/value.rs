@@ -0,0 +1,141 @@
+use super::ast::{BinaryOperator, UnaryOperator};
+use super::class::{LoxClass, LoxInstance};
+use super::function::{LoxFunction, NativeFunction};
+use super::scanner::Token;
+use std::cell::RefCell;
+use std::fmt;
+use std::rc::Rc;
+
+#[derive(Debug, Clone)]
+pub enum Value {
+    Nil,
+    Bool(bool),
+    Number(f64),
+    Str(String),
+    Function(Rc<LoxFunction>),
+    NativeFunction(Rc<NativeFunction>),
+    Class(Rc<LoxClass>),
+    Instance(Rc<RefCell<LoxInstance>>),
+}
+
+impl Value {
+    /// `nil` and `false` are falsey, everything else is truthy
+    pub fn is_truthy(&self) -> bool {
+        !matches!(self, Value::Nil | Value::Bool(false))
+    }
+
+    pub fn unary(operator: UnaryOperator, right: Value) -> Result<Value, &'static str> {
+        match (operator, right) {
+            (UnaryOperator::Negate, Value::Number(value)) => Ok(Value::Number(-value)),
+            (UnaryOperator::Negate, _) => Err("Operand must be a number."),
+            (UnaryOperator::Not, right) => Ok(Value::Bool(!right.is_truthy())),
+        }
+    }
+
+    /// Arithmetic, comparison and equality, there are no implicit conversions between types
+    pub fn binary(operator: BinaryOperator, left: Value, right: Value) -> Result<Value, &'static str> {
+        use BinaryOperator::*;
+        use Value::{Bool, Number, Str};
+
+        match (operator, left, right) {
+            (Equal, left, right) => Ok(Bool(left == right)),
+            (NotEqual, left, right) => Ok(Bool(left != right)),
+
+            (Add, Number(left), Number(right)) => Ok(Number(left + right)),
+            (Add, Str(left), Str(right)) => Ok(Str(left + &right)),
+            (Add, _, _) => Err("Operands must be two numbers or two strings."),
+
+            (Divide, Number(_), Number(right)) | (Remainder, Number(_), Number(right)) if right == 0.0 => {
+                Err("Division by zero.")
+            }
+
+            (Subtract, Number(left), Number(right)) => Ok(Number(left - right)),
+            (Multiply, Number(left), Number(right)) => Ok(Number(left * right)),
+            (Divide, Number(left), Number(right)) => Ok(Number(left / right)),
+            (Remainder, Number(left), Number(right)) => Ok(Number(left % right)),
+
+            (Greater, Number(left), Number(right)) => Ok(Bool(left > right)),
+            (GreaterEqual, Number(left), Number(right)) => Ok(Bool(left >= right)),
+            (Less, Number(left), Number(right)) => Ok(Bool(left < right)),
+            (LessEqual, Number(left), Number(right)) => Ok(Bool(left <= right)),
+
+            _ => Err("Operands must be numbers."),
+        }
+    }
+}
+
+impl PartialEq for Value {
+    /// Values of different types are never equal, functions and objects are compared by identity
+    fn eq(&self, other: &Value) -> bool {
+        match (self, other) {
+            (Value::Nil, Value::Nil) => true,
+            (Value::Bool(left), Value::Bool(right)) => left == right,
+            (Value::Number(left), Value::Number(right)) => left == right,
+            (Value::Str(left), Value::Str(right)) => left == right,
+            (Value::Function(left), Value::Function(right)) => Rc::ptr_eq(left, right),
+            (Value::NativeFunction(left), Value::NativeFunction(right)) => Rc::ptr_eq(left, right),
+            (Value::Class(left), Value::Class(right)) => Rc::ptr_eq(left, right),
+            (Value::Instance(left), Value::Instance(right)) => Rc::ptr_eq(left, right),
+            _ => false,
+        }
+    }
+}
+
+impl<'a> From<&Token<'a>> for Option<Value> {
+    /// Runtime value of a literal token, `None` for any other token
+    fn from(token: &Token<'a>) -> Option<Value> {
+        match token {
+            Token::NumberValue(value) => Some(Value::Number(*value)),
+            Token::StringValue(value) => Some(Value::Str(value.to_string())),
+            Token::True => Some(Value::Bool(true)),
+            Token::False => Some(Value::Bool(false)),
+            Token::Nil => Some(Value::Nil),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Value::Nil => f.write_str("nil"),
+            Value::Bool(value) => write!(f, "{}", value),
+            Value::Number(value) => f.write_str(&format_number(*value)),
+            Value::Str(value) => f.write_str(value),
+            Value::Function(function) => write!(f, "<fn {}>", function.name()),
+            Value::NativeFunction(function) => write!(f, "<native fn {}>", function.name),
+            Value::Class(class) => f.write_str(class.name()),
+            Value::Instance(instance) => write!(f, "{} instance", instance.borrow().class().name()),
+        }
+    }
+}
+
+/// Lox spelling of a number, integral ones without `.0`. Like Java, magnitudes from `1e7` up and below `1e-3`
+/// are in exponent notation, so `1e300` isn't spelled out in 301 digits
+pub(crate) fn format_number(number: f64) -> String {
+    let magnitude = number.abs();
+    if number.is_finite() && number != 0.0 && !(1e-3..1e7).contains(&magnitude) {
+        format!("{:e}", number)
+    } else {
+        format!("{}", number)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn number_spelling() {
+        assert_eq!(format_number(3.0), "3");
+        assert_eq!(format_number(-2.5), "-2.5");
+        assert_eq!(format_number(0.001), "0.001");
+        assert_eq!(format_number(1234567.0), "1234567");
+        assert_eq!(format_number(1e7), "1e7");
+        assert_eq!(format_number(1e300), "1e300");
+        assert_eq!(format_number(-1.5e-7), "-1.5e-7");
+        assert_eq!(format_number(0.0), "0");
+        assert_eq!(format_number(f64::INFINITY), "inf");
+        assert_eq!(Value::Number(1e300).to_string(), "1e300");
+    }
+}
@@ -0,0 +1,77 @@
+use std::env;
+use std::fs;
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{self, Command, Output, Stdio};
+
+fn rlox(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_rlox")).args(args).output().expect("rlox runs")
+}
+
+/// Runs rlox with `input` piped to its stdin
+fn rlox_with_input(args: &[&str], input: &str) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_rlox"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("rlox starts");
+    child.stdin.take().unwrap().write_all(input.as_bytes()).unwrap();
+    child.wait_with_output().expect("rlox runs")
+}
+
+/// Writes `source` to a file of its own, named after the test using it
+fn script(name: &str, source: &str) -> PathBuf {
+    let path = env::temp_dir().join(format!("rlox-{}-{}.lox", process::id(), name));
+    fs::write(&path, source).unwrap();
+    path
+}
+
+#[test]
+fn missing_file() {
+    let output = rlox(&["no/such/script.lox"]);
+    assert_eq!(output.status.code(), Some(66));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.starts_with("Could not read file 'no/such/script.lox': "), "{}", stderr);
+}
+
+#[test]
+fn repl_runs_each_line_fresh() {
+    let output = rlox_with_input(&[], "print 1;\nprint 2;\n");
+    assert_eq!(output.status.code(), Some(0));
+    // The first line would be run again with the second if the input buffer were kept
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "> 1\n> 2\n> ");
+}
+
+#[test]
+fn exit_codes() {
+    let cases = [("ok", "print 1;", 0), ("syntax_error", "print 1", 65), ("runtime_error", "print -nil;", 70)];
+    for (name, source, code) in &cases {
+        let path = script(name, source);
+        let output = rlox(&[path.to_str().unwrap()]);
+        fs::remove_file(&path).unwrap();
+        assert_eq!(output.status.code(), Some(*code), "{}", source);
+    }
+}
+
+#[test]
+fn repl_goes_on_after_errors() {
+    let output = rlox_with_input(&[], "print 1\nprint -nil;\nprint 2;\n");
+    assert_eq!(output.status.code(), Some(0));
+    assert!(String::from_utf8_lossy(&output.stdout).ends_with("2\n> "));
+}
+
+#[test]
+fn repl_waits_for_the_end_of_multi_line_input() {
+    let input = "/* a\ncomment */ print 1;\nprint \"\"\"\n  text\n  \"\"\";\nprint \"${1 +\n2}\";\nprint \"open\nprint 2;\n";
+    let output = rlox_with_input(&[], input);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(stdout, "> ... 1\n> ... ... text\n> ... 3\n> > 2\n> ");
+    // The string is cut off by the line break, so it is reported at once
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Unterminated string."));
+
+    // A blank line is part of a text block, while it abandons open brackets
+    let output = rlox_with_input(&[], "print \"\"\"\na\n\nb\"\"\";\nprint (1\n\nprint 2;\n");
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "> ... ... ... a\n\nb\n> ... > 2\n> ");
+}